@@ -0,0 +1,262 @@
+//! The TOML config, read from `$XDG_CONFIG_HOME/lazyrip/config.toml` and then from the project's
+//! `.lazyrip.toml`, whose settings win:
+//!
+//! ```toml
+//! [keys]
+//! quit = ["ctrl+c", "ctrl+q"]
+//! open = "enter"
+//!
+//! [defaults]
+//! mode = "content"
+//! show_hidden = true
+//! case = "smart"
+//! glob = "!*.lock"
+//!
+//! [layout]
+//! preview_position = "bottom"
+//! preview_split = 60
+//!
+//! [theme]
+//! name = "high-contrast"
+//!
+//! [types]
+//! proto = "*.proto"
+//! web = ["*.html", "*.css", "*.js"]
+//! ```
+//!
+//! See [`crate::theme`] for the theme's settings, `[types]` adds file types as rg's `--type-add`
+//! does.
+//!
+//! Unknown tables and keys are errors, so typos don't go unnoticed.
+
+use crate::keymap::{Action, KeyBinding, Keymap};
+use crate::presets::Preset;
+use crate::results::ripgrep::{Case, Mode};
+use crate::theme::ThemeConfig;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+/// The project config file, meant to be committed so a team shares its settings and presets.
+pub const PROJECT_FILE: &str = ".lazyrip.toml";
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct ConfigFile {
+    keys: HashMap<Action, OneOrMany>,
+    defaults: Defaults,
+    layout: Layout,
+    theme: ThemeConfig,
+    types: BTreeMap<String, OneOrMany>,
+    preset: Vec<Preset>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// The state lazyrip starts in, anything unset keeps lazyrip's own default.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct Defaults {
+    pub mode: Option<Mode>,
+    pub show_hidden: Option<bool>,
+    pub show_preview: Option<bool>,
+    pub watch: Option<bool>,
+    pub multiline: Option<bool>,
+    pub case: Option<Case>,
+    pub glob: Option<String>,
+    /// Vim's keys in the results list: `j`, `k`, `gg`, `G`, `ctrl+d`, `ctrl+u` and `/` unless
+    /// remapped, as the `vim_*`, `half_page_*` and `focus_prompt` actions.
+    pub vim: Option<bool>,
+    /// Selects the first result, and previews it, as soon as it's found.
+    pub auto_select: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct Layout {
+    pub preview_position: Option<PreviewPosition>,
+    /// The share of the results list, in percent, the preview getting the rest.
+    pub preview_split: Option<u16>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewPosition {
+    #[default]
+    Right,
+    Bottom,
+}
+
+impl Layout {
+    pub fn preview_position(&self) -> PreviewPosition {
+        self.preview_position.unwrap_or_default()
+    }
+
+    pub fn preview_split(&self) -> u16 {
+        self.preview_split.unwrap_or(50)
+    }
+}
+
+#[derive(Default)]
+pub struct Config {
+    pub keymap: Keymap,
+    pub defaults: Defaults,
+    pub layout: Layout,
+    pub theme: ThemeConfig,
+    /// Custom file types, as `name:glob`.
+    pub type_definitions: Vec<String>,
+    pub presets: Vec<Preset>,
+}
+
+impl Config {
+    /// Reads the user's and then the project's config, failing on invalid files.
+    pub fn load() -> Result<Config> {
+        let mut config = Config::default();
+        let user_file = dirs::config_dir().map(|dir| dir.join("lazyrip").join("config.toml"));
+        for path in user_file.into_iter().chain([PathBuf::from(PROJECT_FILE)]) {
+            if let Some(file) = Self::read(&path)? {
+                config
+                    .merge(file)
+                    .map_err(|e| Error::other(format!("{}: {}", path.display(), e)))?;
+            }
+        }
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Option<ConfigFile>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        toml::from_str(&content)
+            .map(Some)
+            .map_err(|e| Error::other(format!("{}: {}", path.display(), e)))
+    }
+
+    fn merge(&mut self, file: ConfigFile) -> std::result::Result<(), String> {
+        for (action, keys) in file.keys {
+            let keys = match keys {
+                OneOrMany::One(key) => vec![key],
+                OneOrMany::Many(keys) => keys,
+            };
+            let keys = keys
+                .iter()
+                .map(|k| KeyBinding::parse(k))
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|e| format!("keys: {}", e))?;
+            self.keymap.bind(action, keys);
+        }
+
+        let defaults = file.defaults;
+        let current = &mut self.defaults;
+        current.mode = defaults.mode.or(current.mode);
+        current.show_hidden = defaults.show_hidden.or(current.show_hidden);
+        current.show_preview = defaults.show_preview.or(current.show_preview);
+        current.watch = defaults.watch.or(current.watch);
+        current.multiline = defaults.multiline.or(current.multiline);
+        current.case = defaults.case.or(current.case);
+        current.glob = defaults.glob.or(current.glob.take());
+        current.vim = defaults.vim.or(current.vim);
+        current.auto_select = defaults.auto_select.or(current.auto_select);
+
+        if let Some(split) = file.layout.preview_split {
+            if !(10..=90).contains(&split) {
+                return Err(format!(
+                    "layout.preview_split: {} is not between 10 and 90",
+                    split
+                ));
+            }
+        }
+        let layout = file.layout;
+        self.layout.preview_position = layout.preview_position.or(self.layout.preview_position);
+        self.layout.preview_split = layout.preview_split.or(self.layout.preview_split);
+
+        self.theme.merge(file.theme);
+        for (name, globs) in file.types {
+            if name.is_empty() || name.contains([':', ',']) || name.contains(char::is_whitespace) {
+                return Err(format!("types: invalid type name `{}`", name));
+            }
+            let globs = match globs {
+                OneOrMany::One(glob) => vec![glob],
+                OneOrMany::Many(globs) => globs,
+            };
+            self.type_definitions
+                .extend(globs.iter().map(|glob| format!("{}:{}", name, glob)));
+        }
+        self.presets.extend(file.preset);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(content: &str) -> std::result::Result<Config, String> {
+        let mut config = Config::default();
+        let file = toml::from_str(content).map_err(|e| e.to_string())?;
+        config.merge(file)?;
+        Ok(config)
+    }
+
+    #[test]
+    fn reads_keys_defaults_layout_and_presets() {
+        let config = merge(
+            r#"
+            [keys]
+            quit = ["ctrl+q", "esc"]
+            open = "alt+o"
+
+            [defaults]
+            mode = "regex-files"
+            case = "smart"
+
+            [layout]
+            preview_position = "bottom"
+
+            [types]
+            web = ["*.html", "*.css"]
+
+            [[preset]]
+            name = "TODOs"
+            prompt = "TODO"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.keymap.label(&[Action::Quit, Action::Open]),
+            "<C+q><A+o>"
+        );
+        assert!(config.defaults.mode == Some(Mode::RegexFiles));
+        assert_eq!(config.defaults.case, Some(Case::Smart));
+        assert!(config.layout.preview_position() == PreviewPosition::Bottom);
+        assert_eq!(config.layout.preview_split(), 50);
+        assert_eq!(config.type_definitions, ["web:*.html", "web:*.css"]);
+        assert_eq!(config.presets[0].search.prompt, "TODO");
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        for content in [
+            "[keyz]\nquit = \"ctrl+q\"",
+            "[keys]\nfly = \"ctrl+q\"",
+            "[keys]\nquit = \"ctrl+nope\"",
+            "[defaults]\nhidden = true",
+            "[layout]\npreview_split = 95",
+            "[theme]\nname = \"solarized\"",
+            "[theme]\npath = \"bold purple\"",
+            "[types]\n\"a:b\" = \"*.x\"",
+            "[[preset]]\nname = \"x\"\npromt = \"typo\"",
+        ] {
+            assert!(merge(content).is_err(), "{}", content);
+        }
+    }
+}
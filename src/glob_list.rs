@@ -0,0 +1,435 @@
+use crate::theme::Theme;
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use ratatui::widgets::block::{Position, Title};
+use ratatui::{prelude::*, widgets::*};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use tui_textarea::{CursorMove, Input, Key, TextArea};
+
+/// How many files are listed for the directories and extensions to complete.
+const MAX_COMPLETION_FILES: usize = 10_000;
+
+/// The width of the checkbox and the include/exclude label before each glob.
+const LABEL_WIDTH: u16 = 11;
+
+struct Row {
+    glob: String,
+    enabled: bool,
+}
+
+impl Row {
+    fn error(&self) -> Option<String> {
+        if self.glob.trim().is_empty() {
+            return None;
+        }
+        OverrideBuilder::new("")
+            .add(self.glob.trim())
+            .err()
+            .map(|e| e.to_string())
+    }
+}
+
+/// A file or directory under one of the searched roots, relative to it.
+struct Listed {
+    path: PathBuf,
+    is_dir: bool,
+}
+
+/// The paths completed from, listed in the background when the popup opens.
+enum Listing {
+    Pending(mpsc::Receiver<Vec<Listed>>),
+    Done(Vec<Listed>),
+}
+
+/// The glob popup, one glob per row, each of them can be disabled without deleting it.
+pub struct GlobList<'a> {
+    rows: Vec<Row>,
+    selection: usize,
+    /// Edits the selected row.
+    editor: TextArea<'a>,
+    /// The candidates of an ambiguous completion.
+    message: Option<String>,
+    listing: Listing,
+}
+
+impl<'a> GlobList<'a> {
+    pub fn new() -> GlobList<'a> {
+        let mut list = GlobList {
+            rows: Vec::new(),
+            selection: 0,
+            editor: TextArea::default(),
+            message: None,
+            listing: Listing::Done(Vec::new()),
+        };
+        list.set("");
+        list
+    }
+
+    /// Replaces the rows with the entries of a `;` separated glob, all enabled.
+    pub fn set(&mut self, glob: &str) {
+        self.rows = glob
+            .split(';')
+            .map(str::trim)
+            .filter(|g| !g.is_empty())
+            .map(|g| Row {
+                glob: g.to_string(),
+                enabled: true,
+            })
+            .collect();
+        if self.rows.is_empty() {
+            self.rows.push(Row {
+                glob: String::new(),
+                enabled: true,
+            });
+        }
+        self.select(0);
+    }
+
+    /// The enabled and valid globs, `;` separated as in [`crate::results::ripgrep::Options`].
+    pub fn glob(&self) -> String {
+        self.rows
+            .iter()
+            .filter(|row| row.enabled && !row.glob.trim().is_empty() && row.error().is_none())
+            .map(|row| row.glob.trim())
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+
+    /// Starts listing the paths under `roots`, the current directory when empty, to complete
+    /// them.
+    pub fn open(&mut self, roots: &[String], show_hidden: bool) {
+        self.message = None;
+        let roots = roots.to_vec();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || tx.send(list(&roots, show_hidden)));
+        self.listing = Listing::Pending(rx);
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selection = index.min(self.rows.len() - 1);
+        self.editor = TextArea::from([self.rows[self.selection].glob.clone()]);
+        self.editor
+            .set_placeholder_text("Type a glob, like *.rs or !target/**");
+        self.editor.set_cursor_line_style(Style::default());
+        self.editor.move_cursor(CursorMove::End);
+        self.message = None;
+    }
+
+    pub fn next(&mut self) {
+        self.select(self.selection + 1);
+    }
+
+    pub fn prev(&mut self) {
+        self.select(self.selection.saturating_sub(1));
+    }
+
+    /// Adds an empty row under the selected one.
+    pub fn add(&mut self) {
+        self.rows.insert(
+            self.selection + 1,
+            Row {
+                glob: String::new(),
+                enabled: true,
+            },
+        );
+        self.select(self.selection + 1);
+    }
+
+    pub fn toggle(&mut self) {
+        let row = &mut self.rows[self.selection];
+        row.enabled = !row.enabled;
+    }
+
+    /// Edits the selected row, backspace on an empty row deletes it. Returns whether the globs
+    /// changed.
+    pub fn input(&mut self, input: Input) -> bool {
+        let empty = self.rows[self.selection].glob.is_empty();
+        if matches!(input.key, Key::Backspace) && empty && self.rows.len() > 1 {
+            self.rows.remove(self.selection);
+            self.select(self.selection.saturating_sub(1));
+            return true;
+        }
+        if !self.editor.input(input) {
+            return false;
+        }
+        self.rows[self.selection].glob = self.editor.lines()[0].clone();
+        self.message = None;
+        true
+    }
+
+    /// Completes the directory or the `*.` extension being typed on the selected row, from the
+    /// paths under the searched roots. Returns whether the glob changed.
+    pub fn complete(&mut self) -> bool {
+        if let Listing::Pending(rx) = &self.listing {
+            match rx.try_recv() {
+                Ok(listed) => self.listing = Listing::Done(listed),
+                Err(mpsc::TryRecvError::Empty) => {
+                    self.message = Some("Still listing the files…".to_string());
+                    return false;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => self.listing = Listing::Done(Vec::new()),
+            }
+        }
+        let Listing::Done(listed) = &self.listing else {
+            return false;
+        };
+
+        let glob = self.rows[self.selection].glob.clone();
+        let path = glob.trim_start_matches('!');
+        let (dir, segment) = match path.rfind('/') {
+            Some(i) => path.split_at(i + 1),
+            None => ("", path),
+        };
+        let literal_dir = !dir.contains(['*', '?', '[', '{']);
+        let dir = Path::new(if literal_dir { dir } else { "" });
+
+        let (prefix, candidates, suffix) = match segment.rsplit_once("*.") {
+            Some((_, extension)) => (extension, extensions(listed, dir), ""),
+            None if literal_dir && !segment.contains(['*', '?', '[', '{']) => {
+                (segment, directories(listed, dir), "/")
+            }
+            None => return false,
+        };
+        let matching: Vec<&String> = candidates
+            .iter()
+            .filter(|c| c.starts_with(prefix))
+            .collect();
+        let Some(completed) = common_prefix(&matching) else {
+            self.message = Some("No completions".to_string());
+            return false;
+        };
+        self.message = match matching.len() {
+            1 => None,
+            _ => Some(
+                matching
+                    .iter()
+                    .map(|c| c.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+        };
+
+        let suffix = if matching.len() == 1 { suffix } else { "" };
+        let glob = format!(
+            "{}{}{}",
+            &glob[..glob.len() - prefix.len()],
+            completed,
+            suffix
+        );
+        if glob == self.rows[self.selection].glob {
+            return false;
+        }
+        self.rows[self.selection].glob = glob;
+        self.select_keeping_message();
+        true
+    }
+
+    fn select_keeping_message(&mut self) {
+        let message = self.message.take();
+        self.select(self.selection);
+        self.message = message;
+    }
+
+    /// Renders the popup over `area`, one row per glob with the selected one being edited.
+    pub fn render(&mut self, frame: &mut Frame, area: Rect, block: Block, theme: &Theme) {
+        let error = self.rows[self.selection].error();
+        let block = match (&error, &self.message) {
+            (Some(error), _) => block.title(
+                Title::from(Span::styled(format!(" {} ", error), theme.key))
+                    .position(Position::Bottom),
+            ),
+            (None, Some(message)) => {
+                block.title(Title::from(format!(" {} ", message)).position(Position::Bottom))
+            }
+            (None, None) => block,
+        };
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+
+        let height = inner.height.max(1) as usize;
+        let offset = (self.selection + 1).saturating_sub(height);
+        for (i, row) in self.rows.iter().enumerate().skip(offset).take(height) {
+            let y = inner.y + (i - offset) as u16;
+            let checkbox = if row.enabled { "🗹" } else { "☐" };
+            let (label, style) = if row.error().is_some() {
+                ("invalid", theme.key)
+            } else if row.glob.trim_start().starts_with('!') {
+                ("exclude", theme.path)
+            } else {
+                ("include", theme.line_number)
+            };
+            let style = if row.enabled { style } else { theme.muted };
+            let line = Line::from(vec![
+                Span::raw(format!("{} ", checkbox)),
+                Span::styled(format!("{:<8}", label), style),
+            ]);
+            frame.render_widget(Paragraph::new(line), Rect::new(inner.x, y, inner.width, 1));
+
+            let text_area = Rect::new(
+                inner.x + LABEL_WIDTH,
+                y,
+                inner.width.saturating_sub(LABEL_WIDTH),
+                1,
+            );
+            if i == self.selection {
+                frame.render_widget(self.editor.widget(), text_area);
+            } else {
+                let style = if row.enabled {
+                    Style::default()
+                } else {
+                    theme.muted
+                };
+                frame.render_widget(Paragraph::new(row.glob.as_str()).style(style), text_area);
+            }
+        }
+    }
+
+    /// The height of the popup showing every row, borders included.
+    pub fn height(&self) -> u16 {
+        self.rows.len() as u16 + 2
+    }
+}
+
+/// The files and directories under `roots`, ignored ones left out, up to
+/// [`MAX_COMPLETION_FILES`] files.
+fn list(roots: &[String], show_hidden: bool) -> Vec<Listed> {
+    let roots = match roots {
+        [] => &[".".to_string()][..],
+        roots => roots,
+    };
+    let mut listed = Vec::new();
+    let mut files = 0;
+    for root in roots {
+        for entry in WalkBuilder::new(root)
+            .hidden(!show_hidden)
+            .build()
+            .flatten()
+        {
+            let Some(file_type) = entry.file_type() else {
+                continue;
+            };
+            if entry.depth() == 0 {
+                continue;
+            }
+            if file_type.is_file() {
+                files += 1;
+                if files > MAX_COMPLETION_FILES {
+                    return listed;
+                }
+            }
+            let path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            listed.push(Listed {
+                path: path.to_path_buf(),
+                is_dir: file_type.is_dir(),
+            });
+        }
+    }
+    listed
+}
+
+/// The extensions of the files under `dir`.
+fn extensions(listed: &[Listed], dir: &Path) -> Vec<String> {
+    let mut extensions: Vec<String> = listed
+        .iter()
+        .filter(|entry| !entry.is_dir && entry.path.starts_with(dir))
+        .filter_map(|entry| {
+            let extension = entry.path.extension()?;
+            Some(extension.to_string_lossy().into_owned())
+        })
+        .collect();
+    extensions.sort();
+    extensions.dedup();
+    extensions
+}
+
+/// The names of the directories right under `dir`.
+fn directories(listed: &[Listed], dir: &Path) -> Vec<String> {
+    let mut directories: Vec<String> = listed
+        .iter()
+        .filter(|entry| entry.is_dir && entry.path.parent() == Some(dir))
+        .filter_map(|entry| Some(entry.path.file_name()?.to_str()?.to_string()))
+        .collect();
+    directories.sort();
+    directories.dedup();
+    directories
+}
+
+/// The longest prefix of all of `candidates`, `None` without candidates.
+fn common_prefix(candidates: &[&String]) -> Option<String> {
+    let (first, rest) = candidates.split_first()?;
+    let mut prefix = first.as_str();
+    for candidate in rest {
+        let common = prefix
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(prefix.len().min(candidate.len()), |((i, _), _)| i);
+        prefix = &prefix[..common];
+    }
+    Some(prefix.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_enabled_and_valid_globs() {
+        let mut list = GlobList::new();
+        list.set("*.rs; ;!target/**;[a");
+        assert_eq!(list.rows.len(), 3);
+        assert_eq!(list.glob(), "*.rs;!target/**");
+        assert!(list.rows[2].error().is_some());
+
+        list.toggle();
+        assert_eq!(list.glob(), "!target/**");
+        list.add();
+        assert!(list.input(Input {
+            key: Key::Backspace,
+            ctrl: false,
+            alt: false,
+        }));
+        assert_eq!(list.rows.len(), 3);
+        assert_eq!(list.selection, 0);
+    }
+
+    #[test]
+    fn completes_from_the_paths_under_the_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in [
+            "app/src/main.rs",
+            "app/src/lib.rs",
+            "app/tests/it.rs",
+            "app/build.zig",
+        ] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let root = dir.path().join("app").to_string_lossy().into_owned();
+        let listed = list(&[root], false);
+
+        assert_eq!(directories(&listed, Path::new("")), ["src", "tests"]);
+        assert_eq!(extensions(&listed, Path::new("")), ["rs", "zig"]);
+        assert_eq!(extensions(&listed, Path::new("src/")), ["rs"]);
+
+        let mut list = GlobList::new();
+        list.listing = Listing::Done(listed);
+        list.set("!t");
+        assert!(list.complete());
+        assert_eq!(list.glob(), "!tests/");
+    }
+
+    #[test]
+    fn completes_the_common_prefix() {
+        let candidates = ["rs".to_string(), "rst".to_string(), "rb".to_string()];
+        let refs: Vec<&String> = candidates.iter().collect();
+        assert_eq!(common_prefix(&refs[..2]).as_deref(), Some("rs"));
+        assert_eq!(common_prefix(&refs).as_deref(), Some("r"));
+        assert_eq!(common_prefix(&refs[2..]).as_deref(), Some("rb"));
+        assert_eq!(common_prefix(&[]), None);
+    }
+}
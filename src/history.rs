@@ -0,0 +1,249 @@
+use crate::results::ripgrep::Mode;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Error, Result, Write};
+use std::path::{Path, PathBuf};
+
+/// How many searches are kept, the oldest ones are dropped first.
+const MAX_ENTRIES: usize = 1000;
+/// How many lines the file may grow to before being rewritten without the duplicates and the
+/// dropped searches. Searches are appended to it in between.
+const MAX_LINES: usize = 2 * MAX_ENTRIES;
+
+/// A past search, with everything needed to run it again.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Entry {
+    pub prompt: String,
+    pub glob: String,
+    /// Paths to search, the current directory when empty.
+    pub roots: Vec<String>,
+    pub mode: Mode,
+    pub show_hidden: bool,
+    pub multiline: bool,
+    /// File types to search, as `-t`, and to skip, as `-T`.
+    pub types: Vec<String>,
+    pub types_not: Vec<String>,
+}
+
+/// Past searches, oldest first, persisted as JSON lines in the XDG state directory.
+pub struct History {
+    path: Option<PathBuf>,
+    entries: Vec<Entry>,
+    /// The lines in the file, duplicates and dropped searches included.
+    lines: usize,
+
+    /// The recalled entry while browsing with Up/Down.
+    browsing: Option<usize>,
+    /// The search being edited when browsing started, restored when browsing past the newest.
+    draft: Option<Entry>,
+}
+
+impl History {
+    /// Reads the history file from the XDG state directory.
+    pub fn load() -> History {
+        let path = dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .map(|dir| dir.join("lazyrip").join("history.jsonl"));
+        Self::open(path)
+    }
+
+    /// Reads the history file at `path`, a missing or unreadable file is an empty history. Searches
+    /// run again appear once per run in the file, only the newest is kept.
+    fn open(path: Option<PathBuf>) -> History {
+        let content = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let mut history = History {
+            path,
+            entries: Vec::new(),
+            lines: 0,
+            browsing: None,
+            draft: None,
+        };
+        for line in content.lines() {
+            history.lines += 1;
+            if let Ok(entry) = serde_json::from_str(line) {
+                history.push(entry);
+            }
+        }
+        history
+    }
+
+    /// Records `entry` as the newest search and saves the history.
+    pub fn add(&mut self, entry: Entry) -> Result<()> {
+        self.stop_browsing();
+        if entry.prompt.trim().is_empty() {
+            return Ok(());
+        }
+        let mut line = serde_json::to_vec(&entry).map_err(Error::other)?;
+        line.push(b'\n');
+        self.push(entry);
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        if self.lines >= MAX_LINES {
+            return self.rewrite(&path);
+        }
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?
+            .write_all(&line)?;
+        self.lines += 1;
+        Ok(())
+    }
+
+    /// Makes `entry` the newest one, dropping its older copy and the oldest entries past the limit.
+    fn push(&mut self, entry: Entry) {
+        self.entries.retain(|e| *e != entry);
+        self.entries.push(entry);
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+        }
+    }
+
+    /// Writes the entries to a temporary file then moves it over the file, so that a crash midway
+    /// doesn't lose the history.
+    fn rewrite(&mut self, path: &Path) -> Result<()> {
+        let temp = path.with_extension("jsonl.tmp");
+        let mut file = fs::File::create(&temp)?;
+        for entry in &self.entries {
+            serde_json::to_writer(&mut file, entry).map_err(Error::other)?;
+            file.write_all(b"\n")?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp, path)?;
+        self.lines = self.entries.len();
+        Ok(())
+    }
+
+    pub fn is_browsing(&self) -> bool {
+        self.browsing.is_some()
+    }
+
+    pub fn stop_browsing(&mut self) {
+        self.browsing = None;
+        self.draft = None;
+    }
+
+    /// The entry before the recalled one, `current` is kept as the draft when browsing starts.
+    pub fn older(&mut self, current: Entry) -> Option<&Entry> {
+        let index = match self.browsing {
+            None => {
+                self.draft = Some(current);
+                self.entries.len().checked_sub(1)?
+            }
+            Some(index) => index.checked_sub(1)?,
+        };
+        self.browsing = Some(index);
+        self.entries.get(index)
+    }
+
+    /// The entry after the recalled one, or the draft once past the newest.
+    pub fn newer(&mut self) -> Option<Entry> {
+        let index = self.browsing? + 1;
+        if index < self.entries.len() {
+            self.browsing = Some(index);
+            return Some(self.entries[index].clone());
+        }
+        let draft = self.draft.take();
+        self.stop_browsing();
+        draft
+    }
+
+    /// The entries fuzzy matching `pattern`, best match first and newest first on ties.
+    pub fn search(&self, pattern: &str) -> Vec<&Entry> {
+        let matcher = SkimMatcherV2::default().smart_case();
+        let mut matches: Vec<(i64, &Entry)> = self
+            .entries
+            .iter()
+            .rev()
+            .filter_map(|entry| Some((matcher.fuzzy_match(&entry.prompt, pattern)?, entry)))
+            .collect();
+        if !pattern.is_empty() {
+            matches.sort_by_key(|(score, _)| -score);
+        }
+        matches.into_iter().map(|(_, entry)| entry).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(prompt: &str) -> Entry {
+        Entry {
+            prompt: prompt.to_string(),
+            ..Entry::default()
+        }
+    }
+
+    fn prompts<'a>(entries: impl IntoIterator<Item = &'a Entry>) -> Vec<&'a str> {
+        entries.into_iter().map(|e| e.prompt.as_str()).collect()
+    }
+
+    #[test]
+    fn keeps_the_newest_copy_across_reloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lazyrip").join("history.jsonl");
+        let mut history = History::open(Some(path.clone()));
+        for prompt in ["fn main", "struct", "fn main", "  "] {
+            history.add(entry(prompt)).unwrap();
+        }
+        assert_eq!(prompts(&history.entries), ["struct", "fn main"]);
+
+        let history = History::open(Some(path.clone()));
+        assert_eq!(prompts(&history.entries), ["struct", "fn main"]);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn rewrites_the_file_once_too_long() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let mut history = History::open(Some(path.clone()));
+        for i in 0..MAX_LINES + 1 {
+            history.add(entry(&(i % 10).to_string())).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 10);
+        assert!(!path.with_extension("jsonl.tmp").exists());
+        let history = History::open(Some(path));
+        assert_eq!(history.entries.len(), 10);
+        assert_eq!(history.entries.last().unwrap().prompt, "0");
+    }
+
+    #[test]
+    fn browses_back_to_the_draft() {
+        let mut history = History::open(None);
+        history.add(entry("one")).unwrap();
+        history.add(entry("two")).unwrap();
+
+        assert_eq!(history.older(entry("draft")).unwrap().prompt, "two");
+        assert_eq!(history.older(entry("ignored")).unwrap().prompt, "one");
+        assert!(history.older(entry("ignored")).is_none());
+        assert_eq!(history.newer().unwrap().prompt, "two");
+        assert_eq!(history.newer().unwrap().prompt, "draft");
+        assert!(!history.is_browsing());
+        assert!(history.newer().is_none());
+    }
+
+    #[test]
+    fn searches_best_match_then_newest_first() {
+        let mut history = History::open(None);
+        for prompt in ["foo_bar", "fob", "foo", "unrelated"] {
+            history.add(entry(prompt)).unwrap();
+        }
+        assert_eq!(
+            prompts(history.search("")),
+            ["unrelated", "foo", "fob", "foo_bar"]
+        );
+        assert_eq!(prompts(history.search("foo")), ["foo", "foo_bar"]);
+    }
+}
@@ -0,0 +1,509 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use tui_textarea::{Input, Key};
+
+/// Everything a key can be bound to, named as in the `[keys]` table of the config.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Open,
+    Next,
+    Prev,
+    PageDown,
+    PageUp,
+    /// Selects the first result, only while the results list has the focus.
+    First,
+    /// Selects the last result, only while the results list has the focus.
+    Last,
+    NextFile,
+    PrevFile,
+    /// Moves the focus from the prompt to the results list, then to the preview.
+    ToggleFocus,
+    /// Searches the identifier at the selected match, or at the cursor of the focused preview,
+    /// keeping the current search to go back to.
+    DrillDown,
+    Back,
+    /// Searches only in the file of the selected result.
+    ScopeFile,
+    /// Searches only in the directory of the selected result.
+    ScopeDirectory,
+    ExcludeFile,
+    ExcludeDirectory,
+    /// Undoes the latest scope.
+    PopScope,
+    NewTab,
+    CloseTab,
+    NextTab,
+    PrevTab,
+    SwitchMode,
+    Filter,
+    EditGlob,
+    TogglePreview,
+    ToggleHidden,
+    ToggleMultiline,
+    NewLine,
+    ToggleWatch,
+    History,
+    Presets,
+    /// Picks the file types to search and to skip.
+    FileTypes,
+    Help,
+    /// Closes a popup.
+    Close,
+    /// Picks the selected entry of a popup.
+    Accept,
+    SavePreset,
+    /// Adds a row to the glob popup.
+    AddGlob,
+    /// Enables or disables the selected row of the glob popup.
+    ToggleGlob,
+    /// Completes the directory or extension typed in the glob popup.
+    CompleteGlob,
+    /// Moves the cursor of the focused preview a character to the left.
+    CursorLeft,
+    CursorRight,
+    /// Focuses the results list from the focused preview, and from the prompt with vim's keys.
+    FocusList,
+    /// The next result, or the Nth next after a number. Like the other `vim_*` actions and
+    /// the half page moves, only with vim's keys.
+    VimNext,
+    VimPrev,
+    /// Pressed twice, selects the first result, or the Nth after a number.
+    VimFirst,
+    /// Selects the last result, or the Nth after a number.
+    VimLast,
+    HalfPageDown,
+    HalfPageUp,
+    /// Focuses the prompt from the results list, with vim's keys.
+    FocusPrompt,
+}
+
+impl Action {
+    /// The actions of the main screen, where unbound keys edit the prompt.
+    pub const MAIN: &'static [Action] = &[
+        Action::Quit,
+        Action::Open,
+        Action::Next,
+        Action::Prev,
+        Action::PageDown,
+        Action::PageUp,
+        Action::NextFile,
+        Action::PrevFile,
+        Action::ToggleFocus,
+        Action::DrillDown,
+        Action::Back,
+        Action::ScopeFile,
+        Action::ScopeDirectory,
+        Action::ExcludeFile,
+        Action::ExcludeDirectory,
+        Action::PopScope,
+        Action::NewTab,
+        Action::CloseTab,
+        Action::NextTab,
+        Action::PrevTab,
+        Action::SwitchMode,
+        Action::Filter,
+        Action::EditGlob,
+        Action::TogglePreview,
+        Action::ToggleHidden,
+        Action::ToggleMultiline,
+        Action::NewLine,
+        Action::ToggleWatch,
+        Action::History,
+        Action::Presets,
+        Action::FileTypes,
+        Action::Help,
+    ];
+
+    /// The actions of the main screen while the results list has the focus.
+    pub const LIST: &'static [Action] = &[
+        Action::Quit,
+        Action::Open,
+        Action::Next,
+        Action::Prev,
+        Action::PageDown,
+        Action::PageUp,
+        Action::First,
+        Action::Last,
+        Action::NextFile,
+        Action::PrevFile,
+        Action::ToggleFocus,
+        Action::DrillDown,
+        Action::Back,
+        Action::ScopeFile,
+        Action::ScopeDirectory,
+        Action::ExcludeFile,
+        Action::ExcludeDirectory,
+        Action::PopScope,
+        Action::NewTab,
+        Action::CloseTab,
+        Action::NextTab,
+        Action::PrevTab,
+        Action::SwitchMode,
+        Action::Filter,
+        Action::EditGlob,
+        Action::TogglePreview,
+        Action::ToggleHidden,
+        Action::ToggleMultiline,
+        Action::ToggleWatch,
+        Action::History,
+        Action::Presets,
+        Action::FileTypes,
+        Action::Help,
+    ];
+
+    /// The actions of the main screen while the preview has the focus, moving its cursor.
+    pub const PREVIEW: &'static [Action] = &[
+        Action::Quit,
+        Action::Next,
+        Action::Prev,
+        Action::PageDown,
+        Action::PageUp,
+        Action::First,
+        Action::Last,
+        Action::ToggleFocus,
+        Action::DrillDown,
+        Action::Back,
+        Action::NewTab,
+        Action::CloseTab,
+        Action::NextTab,
+        Action::PrevTab,
+        Action::TogglePreview,
+        Action::Help,
+        Action::CursorLeft,
+        Action::CursorRight,
+        Action::FocusList,
+    ];
+
+    /// Vim's keys, in the results list when they're enabled.
+    pub const VIM: &'static [Action] = &[
+        Action::VimNext,
+        Action::VimPrev,
+        Action::VimFirst,
+        Action::VimLast,
+        Action::HalfPageDown,
+        Action::HalfPageUp,
+        Action::FocusPrompt,
+    ];
+
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Action::Quit => &["ctrl+c"],
+            Action::Open => &["enter", "ctrl+m"],
+            Action::Next => &["down"],
+            Action::Prev => &["up"],
+            Action::PageDown => &["pagedown"],
+            Action::PageUp => &["pageup"],
+            Action::First => &["home"],
+            Action::Last => &["end"],
+            Action::NextFile => &["alt+down"],
+            Action::PrevFile => &["alt+up"],
+            Action::ToggleFocus => &["tab"],
+            Action::DrillDown => &["alt+right"],
+            Action::Back => &["alt+left"],
+            Action::ScopeFile => &["alt+s"],
+            Action::ScopeDirectory => &["alt+a"],
+            Action::ExcludeFile => &["alt+x"],
+            Action::ExcludeDirectory => &["alt+z"],
+            Action::PopScope => &["alt+u"],
+            Action::NewTab => &["ctrl+t"],
+            Action::CloseTab => &["alt+w"],
+            Action::NextTab => &["alt+n"],
+            Action::PrevTab => &["alt+p"],
+            Action::SwitchMode => &["ctrl+f"],
+            Action::Filter => &["ctrl+n"],
+            Action::EditGlob => &["ctrl+g"],
+            Action::TogglePreview => &["ctrl+p"],
+            Action::ToggleHidden => &["ctrl+h"],
+            Action::ToggleMultiline => &["ctrl+l"],
+            Action::NewLine => &["alt+enter"],
+            Action::ToggleWatch => &["ctrl+w"],
+            Action::History => &["ctrl+r"],
+            Action::Presets => &["ctrl+o"],
+            Action::FileTypes => &["ctrl+y"],
+            Action::Help => &["f1", "?"],
+            Action::Close => &["esc", "ctrl+c"],
+            Action::Accept => &["enter", "ctrl+m"],
+            Action::SavePreset => &["ctrl+s"],
+            Action::AddGlob => &["enter", "ctrl+m"],
+            Action::ToggleGlob => &["ctrl+space"],
+            Action::CompleteGlob => &["tab"],
+            Action::CursorLeft => &["left"],
+            Action::CursorRight => &["right"],
+            Action::FocusList => &["esc"],
+            Action::VimNext => &["j"],
+            Action::VimPrev => &["k"],
+            Action::VimFirst => &["g"],
+            Action::VimLast => &["G"],
+            Action::HalfPageDown => &["ctrl+d"],
+            Action::HalfPageUp => &["ctrl+u"],
+            Action::FocusPrompt => &["/"],
+        }
+    }
+
+    /// What the action does, as listed in the help.
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::Open => "Open the selected result in $EDITOR",
+            Action::Next => "Select the next result, or a newer search from the history",
+            Action::Prev => "Select the previous result, or an older search from the history",
+            Action::PageDown => "Select the result a page down",
+            Action::PageUp => "Select the result a page up",
+            Action::First => "Select the first result",
+            Action::Last => "Select the last result",
+            Action::NextFile => "Select the first result of the next file",
+            Action::PrevFile => "Select the first result of the previous file",
+            Action::ToggleFocus => "Move the focus between the prompt, the results and the preview",
+            Action::DrillDown => "Search the identifier at the selected match or preview cursor",
+            Action::Back => "Go back to the search before the last drill down",
+            Action::ScopeFile => "Search only in the file of the selected result",
+            Action::ScopeDirectory => "Search only in the directory of the selected result",
+            Action::ExcludeFile => "Stop searching in the file of the selected result",
+            Action::ExcludeDirectory => "Stop searching in the directory of the selected result",
+            Action::PopScope => "Undo the latest scope",
+            Action::NewTab => "Open a search in a new tab, with the current options",
+            Action::CloseTab => "Close the current tab",
+            Action::NextTab => "Switch to the next tab",
+            Action::PrevTab => "Switch to the previous tab",
+            Action::SwitchMode => "Switch between content and file search modes",
+            Action::Filter => "Fuzzy filter the results",
+            Action::EditGlob => "Edit the glob",
+            Action::TogglePreview => "Show or hide the preview",
+            Action::ToggleHidden => "Search in hidden files",
+            Action::ToggleMultiline => "Search across lines",
+            Action::NewLine => "Add a line to a multiline prompt",
+            Action::ToggleWatch => "Re-search files as they change",
+            Action::History => "Search the history",
+            Action::Presets => "Pick or save a preset",
+            Action::FileTypes => "Pick the file types to search and to skip",
+            Action::Help => "Show this help",
+            Action::Close => "Close a popup",
+            Action::Accept => "Pick the selected entry of a popup",
+            Action::SavePreset => "Save the current search as a preset",
+            Action::AddGlob => "Add a glob under the selected one",
+            Action::ToggleGlob => "Enable or disable the selected glob",
+            Action::CompleteGlob => "Complete the directory or extension of the selected glob",
+            Action::CursorLeft => "Move the cursor of the preview left",
+            Action::CursorRight => "Move the cursor of the preview right",
+            Action::FocusList => "Focus the results from the preview, or from the prompt with vim",
+            Action::VimNext => "Select the next result, N times after a number",
+            Action::VimPrev => "Select the previous result, N times after a number",
+            Action::VimFirst => "Twice: select the first result, or the Nth after a number",
+            Action::VimLast => "Select the last result, or the Nth after a number",
+            Action::HalfPageDown => "Move half a page down",
+            Action::HalfPageUp => "Move half a page up",
+            Action::FocusPrompt => "Focus the prompt",
+        }
+    }
+
+    pub const ALL: &'static [Action] = &[
+        Action::Quit,
+        Action::Open,
+        Action::Next,
+        Action::Prev,
+        Action::PageDown,
+        Action::PageUp,
+        Action::First,
+        Action::Last,
+        Action::NextFile,
+        Action::PrevFile,
+        Action::ToggleFocus,
+        Action::DrillDown,
+        Action::Back,
+        Action::ScopeFile,
+        Action::ScopeDirectory,
+        Action::ExcludeFile,
+        Action::ExcludeDirectory,
+        Action::PopScope,
+        Action::NewTab,
+        Action::CloseTab,
+        Action::NextTab,
+        Action::PrevTab,
+        Action::SwitchMode,
+        Action::Filter,
+        Action::EditGlob,
+        Action::TogglePreview,
+        Action::ToggleHidden,
+        Action::ToggleMultiline,
+        Action::NewLine,
+        Action::ToggleWatch,
+        Action::History,
+        Action::Presets,
+        Action::FileTypes,
+        Action::Help,
+        Action::Close,
+        Action::Accept,
+        Action::SavePreset,
+        Action::AddGlob,
+        Action::ToggleGlob,
+        Action::CompleteGlob,
+        Action::CursorLeft,
+        Action::CursorRight,
+        Action::FocusList,
+        Action::VimNext,
+        Action::VimPrev,
+        Action::VimFirst,
+        Action::VimLast,
+        Action::HalfPageDown,
+        Action::HalfPageUp,
+        Action::FocusPrompt,
+    ];
+}
+
+/// A key with its modifiers, written `ctrl+c`, `alt+enter`, `down` or `?` in the config.
+#[derive(Clone, Copy, Debug)]
+pub struct KeyBinding {
+    key: Key,
+    ctrl: bool,
+    alt: bool,
+}
+
+impl KeyBinding {
+    pub fn parse(s: &str) -> Result<KeyBinding, String> {
+        let mut binding = KeyBinding {
+            key: Key::Null,
+            ctrl: false,
+            alt: false,
+        };
+        let mut rest = s;
+        loop {
+            let lower = rest.to_lowercase();
+            if lower.starts_with("ctrl+") && rest.len() > 5 {
+                binding.ctrl = true;
+                rest = &rest[5..];
+            } else if lower.starts_with("alt+") && rest.len() > 4 {
+                binding.alt = true;
+                rest = &rest[4..];
+            } else {
+                break;
+            }
+        }
+
+        let mut chars = rest.chars();
+        binding.key = match (chars.next(), chars.next()) {
+            (Some(c), None) => Key::Char(c),
+            _ => match &rest.to_lowercase()[..] {
+                "enter" => Key::Enter,
+                "esc" => Key::Esc,
+                "tab" => Key::Tab,
+                "backspace" => Key::Backspace,
+                "delete" => Key::Delete,
+                "up" => Key::Up,
+                "down" => Key::Down,
+                "left" => Key::Left,
+                "right" => Key::Right,
+                "home" => Key::Home,
+                "end" => Key::End,
+                "pageup" => Key::PageUp,
+                "pagedown" => Key::PageDown,
+                "space" => Key::Char(' '),
+                f => match f.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n @ 1..=12) => Key::F(n),
+                    _ => return Err(format!("unknown key `{}`", s)),
+                },
+            },
+        };
+        Ok(binding)
+    }
+
+    pub fn matches(&self, input: &Input) -> bool {
+        let same_key = match (self.key, input.key) {
+            (Key::Char(a), Key::Char(b)) => a == b,
+            (Key::F(a), Key::F(b)) => a == b,
+            (a, b) => std::mem::discriminant(&a) == std::mem::discriminant(&b),
+        };
+        same_key && self.ctrl == input.ctrl && self.alt == input.alt
+    }
+}
+
+/// Written the way the footer shows keys: `<C+f>`, `<A+ENTER>`, `↑` or `?`.
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let key = match self.key {
+            Key::Char(' ') => "SPACE".to_string(),
+            Key::Char(c) => c.to_string(),
+            Key::F(n) => format!("F{}", n),
+            Key::Up => "↑".to_string(),
+            Key::Down => "↓".to_string(),
+            Key::Left => "←".to_string(),
+            Key::Right => "→".to_string(),
+            key => format!("{:?}", key).to_uppercase(),
+        };
+        match (self.ctrl, self.alt) {
+            (false, false) => write!(f, "{}", key),
+            (ctrl, alt) => write!(
+                f,
+                "<{}{}{}>",
+                if ctrl { "C+" } else { "" },
+                if alt { "A+" } else { "" },
+                key
+            ),
+        }
+    }
+}
+
+/// The keys bound to each action, the defaults unless remapped in the config.
+pub struct Keymap {
+    bindings: HashMap<Action, Vec<KeyBinding>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let bindings = Action::ALL
+            .iter()
+            .map(|&action| {
+                let keys = action.default_keys().iter();
+                let keys = keys.map(|k| KeyBinding::parse(k).unwrap()).collect();
+                (action, keys)
+            })
+            .collect();
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    /// Replaces the keys of `action`.
+    pub fn bind(&mut self, action: Action, keys: Vec<KeyBinding>) {
+        self.bindings.insert(action, keys);
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyBinding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// The first of `actions` bound to `input`.
+    pub fn action(&self, input: &Input, actions: &[Action]) -> Option<Action> {
+        actions
+            .iter()
+            .copied()
+            .find(|&action| self.is(action, input))
+    }
+
+    /// Like [`Keymap::action`], ignoring plain characters, which are typed into the prompt.
+    pub fn typing_action(&self, input: &Input, actions: &[Action]) -> Option<Action> {
+        match input {
+            Input {
+                key: Key::Char(_),
+                ctrl: false,
+                alt: false,
+            } => None,
+            _ => self.action(input, actions),
+        }
+    }
+
+    pub fn is(&self, action: Action, input: &Input) -> bool {
+        self.keys(action).iter().any(|k| k.matches(input))
+    }
+
+    /// The keys of `actions`, as shown in the footer, e.g. `↑↓` or `<C+c>`.
+    pub fn label(&self, actions: &[Action]) -> String {
+        actions
+            .iter()
+            .filter_map(|&action| self.keys(action).first())
+            .map(ToString::to_string)
+            .collect()
+    }
+}
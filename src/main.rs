@@ -1,65 +1,431 @@
+mod config;
+mod glob_list;
+mod history;
+mod keymap;
+mod picker;
+mod presets;
 mod results;
+mod theme;
 
+use clap::{Parser, Subcommand, ValueEnum};
+use config::{Config, PreviewPosition};
 use crossterm::{
-    event,
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use glob_list::GlobList;
+use history::History;
+use keymap::{Action, Keymap};
+use picker::Picker;
+use presets::Presets;
 use ratatui::{prelude::*, widgets::*};
+use results::backend::{self, CommandBackend, OutputFormat, SearchBackend};
+use results::index::Index;
+use results::ripgrep::{self, FileType, Mode};
+use results::ScopeKind;
 use std::io::{self, stdout};
-use tui_textarea::{Input, Key, TextArea};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use theme::Theme;
+use tui_textarea::{CursorMove, Input, Key, TextArea};
+
+#[derive(Parser)]
+#[command(version, about = "A TUI for searching code with ripgrep")]
+struct Cli {
+    #[command(subcommand)]
+    subcommand: Option<Subcommands>,
+
+    /// Search backend, defaults to the in-process engine when the directory is indexed or rg isn't
+    /// installed, and to rg otherwise
+    #[arg(long, value_enum)]
+    backend: Option<Backend>,
+
+    /// Shell command run by the `command` backend, the query is appended to it as an argument
+    #[arg(long, required_if_eq("backend", "command"))]
+    command: Option<String>,
+
+    /// Output format of the `command` backend
+    #[arg(long, value_enum, default_value = "vimgrep")]
+    format: OutputFormat,
+
+    /// Re-search files as they change on disk
+    #[arg(long)]
+    watch: bool,
+
+    /// Use no colors, as when NO_COLOR is set
+    #[arg(long)]
+    monochrome: bool,
+}
+
+#[derive(Subcommand)]
+enum Subcommands {
+    /// Build or update the trigram index of the current directory, used by the in-process backend
+    Index,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Backend {
+    Rg,
+    GitGrep,
+    InProcess,
+    Command,
+}
+
+impl Cli {
+    fn build_backend(&self) -> Box<dyn SearchBackend> {
+        let backend = self.backend.unwrap_or(
+            if !Index::default_cache().is_ok_and(|cache| Index::exists(&cache, Path::new(".")))
+                && backend::is_installed("rg")
+            {
+                Backend::Rg
+            } else {
+                Backend::InProcess
+            },
+        );
+
+        match backend {
+            Backend::Rg => Box::new(results::ripgrep::Ripgrep),
+            Backend::GitGrep => Box::new(results::git_grep::GitGrep),
+            Backend::InProcess => Box::new(results::in_process::InProcess::new()),
+            Backend::Command => Box::new(CommandBackend::new(
+                self.command.clone().unwrap_or_default(),
+                self.format,
+            )),
+        }
+    }
+}
 
 struct App<'a> {
     should_quit: bool,
     should_restart_terminal: bool,
     show_glob: bool,
+    show_filter: bool,
+    show_history: bool,
+    show_presets: bool,
+    show_types: bool,
+    show_help: bool,
+    help_scroll: u16,
 
     prompt: TextArea<'a>,
-    glob: TextArea<'a>,
+    globs: GlobList<'a>,
+    filter: TextArea<'a>,
+
+    history: History,
+    history_picker: Picker<'a>,
+    presets: Presets,
+    presets_picker: Picker<'a>,
+    types_picker: Picker<'a>,
+    /// The file types listed by the types popup, read again each time it opens.
+    file_types: Vec<FileType>,
+
+    keymap: Keymap,
+    layout: config::Layout,
+    theme: Theme,
+
+    focus: Focus,
+    /// The searches drilled down from, the latest last.
+    trail: Vec<TrailEntry<'a>>,
+    /// The other tabs, the current one being `None` as its search is the one shown.
+    tabs: Vec<Option<Tab<'a>>>,
+    current_tab: usize,
+    /// Whether vim's keys work in the results list.
+    vim: bool,
+    /// A number typed in the results list, the result to jump to.
+    count: Option<usize>,
+    /// Whether `g` was just typed, `gg` jumping to the first result.
+    pending_g: bool,
+
+    areas: Areas,
+    last_draw: Instant,
+    /// The time and result of the last click, to open the result on a double click.
+    last_click: Option<(Instant, usize)>,
+    /// Whether the divider between the results and the preview is being dragged.
+    dragging_split: bool,
 
     results_manager: results::Manager<'a>,
 }
 
+/// Where keys go: the prompt edits the search, the results list has its own navigation keys and
+/// the preview moves a cursor to drill down from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Prompt,
+    List,
+    Preview,
+}
+
+/// A search drilled down from, with what's needed to show it again as it was.
+struct TrailEntry<'a> {
+    entry: history::Entry,
+    filter: String,
+    focus: Focus,
+    search: results::SavedSearch<'a>,
+}
+
+/// A tab in the background, with everything needed to switch back to it.
+struct Tab<'a> {
+    entry: history::Entry,
+    filter: String,
+    focus: Focus,
+    trail: Vec<TrailEntry<'a>>,
+    search: results::SavedSearch<'a>,
+}
+
+/// Where the panes were last drawn, for hit-testing mouse events.
+#[derive(Default)]
+struct Areas {
+    hidden_box: Rect,
+    multiline_box: Rect,
+    watch_box: Rect,
+    results: Rect,
+    /// The index of the first result shown, the list scrolls to keep the selection visible.
+    results_offset: usize,
+    preview: Option<Rect>,
+    /// The results and the preview together.
+    body: Rect,
+    /// The title of each tab in the tab bar, empty with a single tab.
+    tabs: Vec<Rect>,
+}
+
+const SPINNER: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+/// How often the spinner turns while searching.
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Two clicks on the same result within this time open it.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// The footer's hints while typing the search, most relevant first as only the ones fitting the
+/// width are shown.
+const PROMPT_FOOTER: &[(&[Action], &str)] = &[
+    (&[Action::Help], "Help"),
+    (&[Action::Prev, Action::Next], "Navigate results"),
+    (&[Action::Open], "Open file"),
+    (&[Action::ToggleFocus], "Focus results"),
+    (&[Action::DrillDown], "Search word"),
+    (&[Action::Back], "Back"),
+    (&[Action::NewTab], "New tab"),
+    (&[Action::PrevTab, Action::NextTab], "Switch tab"),
+    (&[Action::SwitchMode], "Switch content/file search"),
+    (&[Action::Filter], "Filter results"),
+    (&[Action::EditGlob], "Edit glob"),
+    (&[Action::History], "History"),
+    (&[Action::Presets], "Presets"),
+    (&[Action::FileTypes], "File types"),
+    (&[Action::TogglePreview], "Toggle preview"),
+    (&[Action::ToggleHidden], "Toggle search in hidden files"),
+    (&[Action::ToggleMultiline], "Toggle multiline"),
+    (&[Action::ToggleWatch], "Toggle watch"),
+    (&[Action::Quit], "Quit"),
+];
+
+const MULTILINE_FOOTER: &[(&[Action], &str)] = &[
+    (&[Action::Help], "Help"),
+    (&[Action::NewLine], "New line"),
+    (&[Action::ToggleMultiline], "Toggle multiline"),
+    (&[Action::Prev, Action::Next], "Navigate results"),
+    (&[Action::Open], "Open file"),
+    (&[Action::ToggleFocus], "Focus results"),
+    (&[Action::Quit], "Quit"),
+];
+
+const LIST_FOOTER: &[(&[Action], &str)] = &[
+    (&[Action::Help], "Help"),
+    (&[Action::Prev, Action::Next], "Navigate results"),
+    (&[Action::Open], "Open file"),
+    (&[Action::DrillDown], "Search word"),
+    (&[Action::Back], "Back"),
+    (&[Action::ScopeFile], "Search in file"),
+    (&[Action::ScopeDirectory], "Search in directory"),
+    (
+        &[Action::ExcludeFile, Action::ExcludeDirectory],
+        "Exclude file/directory",
+    ),
+    (&[Action::PopScope], "Pop scope"),
+    (&[Action::PageUp, Action::PageDown], "Page"),
+    (&[Action::PrevFile, Action::NextFile], "Previous/next file"),
+    (&[Action::First, Action::Last], "First/last"),
+    (&[Action::PrevTab, Action::NextTab], "Switch tab"),
+    (&[Action::ToggleFocus], "Focus preview/prompt"),
+    (&[Action::Filter], "Filter results"),
+    (&[Action::Quit], "Quit"),
+];
+
+const PREVIEW_FOOTER: &[(&[Action], &str)] = &[
+    (&[Action::Help], "Help"),
+    (
+        &[
+            Action::Prev,
+            Action::Next,
+            Action::CursorLeft,
+            Action::CursorRight,
+        ],
+        "Move cursor",
+    ),
+    (&[Action::DrillDown], "Search word"),
+    (&[Action::Back], "Back"),
+    (&[Action::PageUp, Action::PageDown], "Page"),
+    (&[Action::FocusList], "Focus results"),
+    (&[Action::ToggleFocus], "Focus prompt"),
+    (&[Action::Quit], "Quit"),
+];
+
+const FILTER_FOOTER: &[(&[Action], &str)] = &[
+    (&[Action::Prev, Action::Next], "Navigate results"),
+    (&[Action::Accept], "Done"),
+    (&[Action::Close], "Close filter"),
+];
+
+const GLOB_FOOTER: &[(&[Action], &str)] = &[
+    (&[Action::AddGlob], "New glob"),
+    (&[Action::ToggleGlob], "Enable/disable"),
+    (&[Action::CompleteGlob], "Complete path"),
+    (&[Action::Prev, Action::Next], "Navigate"),
+    (&[Action::Close], "Done"),
+];
+
+const HISTORY_FOOTER: &[(&[Action], &str)] = &[
+    (&[Action::Prev, Action::Next], "Navigate"),
+    (&[Action::Accept], "Run search"),
+    (&[Action::Close], "Close"),
+];
+
+const PRESETS_FOOTER: &[(&[Action], &str)] = &[
+    (&[Action::Prev, Action::Next], "Navigate"),
+    (&[Action::Accept], "Run preset"),
+    (&[Action::SavePreset], "Save current search"),
+    (&[Action::Close], "Close"),
+];
+
+const TYPES_FOOTER: &[(&[Action], &str)] = &[
+    (&[Action::Prev, Action::Next], "Navigate"),
+    (&[Action::Accept], "Search/skip/clear type"),
+    (&[Action::Close], "Done"),
+];
+
+const HELP_FOOTER: &[(&[Action], &str)] = &[
+    (&[Action::Prev, Action::Next], "Scroll"),
+    (&[Action::Close], "Close help"),
+];
+
 impl<'a> App<'a> {
-    fn new() -> App<'a> {
+    fn new(backend: Box<dyn SearchBackend>, config: Config, theme: Theme) -> io::Result<App<'a>> {
         let mut textarea = TextArea::default();
         textarea.set_placeholder_text("Start typing to search...");
-        textarea.set_block(Self::default_block());
+        textarea.set_block(Self::default_block(&theme));
         textarea.set_cursor_line_style(Style::default());
 
-        let mut glob_textarea = TextArea::default();
-        glob_textarea.set_placeholder_text("Empty");
-        glob_textarea.set_block(
-            Self::default_block()
-                .title(" Glob (use ; to separate multiple) ")
+        let mut filter_textarea = TextArea::default();
+        filter_textarea.set_placeholder_text("Type to fuzzy filter the results...");
+        filter_textarea.set_block(Self::default_block(&theme).title(" Filter results "));
+        filter_textarea.set_cursor_line_style(Style::default());
+
+        let history_picker = Picker::new(
+            Self::default_block(&theme)
+                .title(" History ")
+                .title_alignment(Alignment::Center),
+            "Type to search past queries...",
+        );
+        let presets_picker = Picker::new(
+            Self::default_block(&theme)
+                .title(" Presets ")
+                .title_alignment(Alignment::Center),
+            "Type to search presets, or a name to save the current search as...",
+        );
+
+        let types_picker = Picker::new(
+            Self::default_block(&theme)
+                .title(" File types ")
                 .title_alignment(Alignment::Center),
+            "Type to search file types...",
         );
-        glob_textarea.set_cursor_line_style(Style::default());
 
-        App {
+        let mut app = App {
             prompt: textarea,
-            glob: glob_textarea,
+            globs: GlobList::new(),
+            filter: filter_textarea,
+            history: History::load(),
+            history_picker,
+            presets: Presets::new(config.presets),
+            presets_picker,
+            types_picker,
+            file_types: Vec::new(),
             should_quit: false,
             should_restart_terminal: false,
             show_glob: false,
-            results_manager: results::Manager::new(),
+            show_filter: false,
+            show_history: false,
+            show_presets: false,
+            show_types: false,
+            show_help: false,
+            help_scroll: 0,
+            keymap: config.keymap,
+            layout: config.layout,
+            results_manager: results::Manager::new(backend, theme.clone()),
+            theme,
+            focus: Focus::Prompt,
+            trail: Vec::new(),
+            tabs: vec![None],
+            current_tab: 0,
+            vim: false,
+            count: None,
+            pending_g: false,
+            areas: Areas::default(),
+            last_draw: Instant::now(),
+            last_click: None,
+            dragging_split: false,
+        };
+        app.results_manager
+            .set_type_definitions(config.type_definitions);
+        app.apply_defaults(config.defaults)?;
+        Ok(app)
+    }
+
+    fn apply_defaults(&mut self, defaults: config::Defaults) -> io::Result<()> {
+        let manager = &mut self.results_manager;
+        if let Some(mode) = defaults.mode {
+            manager.set_mode(mode);
+        }
+        if let Some(case) = defaults.case {
+            manager.set_default_case(case);
+        }
+        manager.set_hidden(defaults.show_hidden.unwrap_or(false));
+        manager.set_multiline(defaults.multiline.unwrap_or(false));
+        manager.show_preview = defaults.show_preview.unwrap_or(true);
+        self.vim = defaults.vim.unwrap_or(false);
+        manager.set_auto_select(defaults.auto_select.unwrap_or(false));
+        if let Some(glob) = defaults.glob {
+            self.globs.set(&glob);
+            self.results_manager.set_glob(glob);
         }
+        if defaults.watch.unwrap_or(false) {
+            self.results_manager.toggle_watch();
+        }
+        Ok(())
     }
 
-    fn default_block() -> Block<'static> {
+    fn default_block(theme: &Theme) -> Block<'static> {
         Block::default()
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded)
+            .border_style(theme.border)
     }
 
     fn startup(&mut self) -> io::Result<()> {
         enable_raw_mode()?;
         stdout().execute(EnterAlternateScreen)?;
+        stdout().execute(EnableMouseCapture)?;
         Ok(())
     }
 
     fn shutdown(&self) -> io::Result<()> {
         disable_raw_mode()?;
+        stdout().execute(DisableMouseCapture)?;
         stdout().execute(LeaveAlternateScreen)?;
         Ok(())
     }
@@ -74,6 +440,7 @@ impl<'a> App<'a> {
                 terminal.draw(|f| {
                     self.ui(f);
                 })?;
+                self.last_draw = Instant::now();
                 match self.handle_events() {
                     Ok(_) => (),
                     e => {
@@ -98,14 +465,32 @@ impl<'a> App<'a> {
 
             if event::poll(std::time::Duration::from_millis(20))? {
                 should_rerender = true;
-                if self.show_glob {
-                    self.glob_mode()?;
+                let event = event::read()?;
+                if self.show_help {
+                    self.help_mode(event.into());
+                } else if self.show_history {
+                    self.history_mode(event.into())?;
+                } else if self.show_presets {
+                    self.presets_mode(event.into())?;
+                } else if self.show_types {
+                    self.types_mode(event.into());
+                } else if self.show_glob {
+                    self.glob_mode(event.into())?;
+                } else if let Event::Mouse(mouse) = event {
+                    self.mouse_mode(mouse)?;
+                } else if self.show_filter {
+                    self.filter_mode(event.into())?;
                 } else {
-                    self.main_mode()?;
+                    self.main_mode(event.into())?;
                 }
             }
 
             should_rerender = self.results_manager.update()? || should_rerender;
+            // Keep the spinner turning while a search finds nothing for a while.
+            let searching = self.results_manager.status().is_some_and(|s| s.running);
+            if searching && self.last_draw.elapsed() >= SPINNER_INTERVAL {
+                should_rerender = true;
+            }
 
             if should_rerender {
                 break;
@@ -114,43 +499,159 @@ impl<'a> App<'a> {
         Ok(())
     }
 
-    fn main_mode(&mut self) -> io::Result<()> {
-        match event::read()?.into() {
-            Input {
-                key: Key::Char('c'),
-                ctrl: true,
-                ..
-            } => self.should_quit = true,
-            Input {
-                key: Key::Char('h'),
-                ctrl: true,
-                ..
-            } => self.results_manager.toggle_hidden(),
-            Input {
-                key: Key::Char('p'),
-                ctrl: true,
-                ..
-            } => self.results_manager.toggle_preview()?,
-            Input {
-                key: Key::Char('g'),
-                ctrl: true,
-                ..
-            } => self.show_glob = true,
-            Input { key: Key::Down, .. } => self.results_manager.next()?,
-            Input { key: Key::Up, .. } => self.results_manager.prev()?,
-            Input { key: Key::Esc, .. } => (),
-            Input {
-                key: Key::Enter, ..
-            }
-            | Input {
-                key: Key::Char('m'),
-                ctrl: true,
-                ..
-            } => self.should_restart_terminal = self.results_manager.open_selection(),
-            input => {
+    fn main_mode(&mut self, input: Input) -> io::Result<()> {
+        // The preview can go away while focused, e.g. when it's toggled.
+        if self.focus == Focus::Preview && !self.results_manager.has_preview() {
+            self.set_focus(Focus::List);
+        }
+        let vim = |actions| if self.vim { actions } else { &[][..] };
+        let action = match self.focus {
+            Focus::Prompt => self
+                .keymap
+                .typing_action(&input, Action::MAIN)
+                .or_else(|| self.keymap.typing_action(&input, vim(&[Action::FocusList]))),
+            Focus::List => self
+                .keymap
+                .action(&input, Action::LIST)
+                .or_else(|| self.keymap.action(&input, vim(Action::VIM))),
+            Focus::Preview => self.keymap.action(&input, Action::PREVIEW),
+        };
+        // A number or a first `g` only applies to the next key.
+        let pending_g = std::mem::take(&mut self.pending_g);
+        let count = self.count.take();
+        let page = self.areas.results.height.saturating_sub(2).max(1) as isize;
+        let half_page = (page / 2).max(1);
+        match action {
+            Some(Action::Quit) => {
+                self.save_to_history();
+                self.should_quit = true;
+            }
+            Some(Action::History) => {
+                self.history_picker.open();
+                self.show_history = true;
+            }
+            Some(Action::Presets) => {
+                self.presets_picker.open();
+                self.show_presets = true;
+            }
+            Some(Action::FileTypes) => {
+                self.file_types = ripgrep::file_types(self.results_manager.type_definitions());
+                self.types_picker.open();
+                self.show_types = true;
+            }
+            Some(Action::Help) => {
+                self.help_scroll = 0;
+                self.show_help = true;
+            }
+            Some(Action::ToggleHidden) => self.results_manager.toggle_hidden(),
+            Some(Action::TogglePreview) => self.results_manager.toggle_preview()?,
+            Some(Action::ToggleWatch) => self.results_manager.toggle_watch(),
+            Some(Action::SwitchMode) => self.results_manager.toggle_mode(),
+            Some(Action::ToggleMultiline) => {
+                self.results_manager.toggle_multiline();
+                self.update_prompt();
+            }
+            Some(Action::EditGlob) => {
+                self.globs.open(
+                    self.results_manager.roots(),
+                    self.results_manager.is_showing_hidden(),
+                );
+                self.show_glob = true;
+            }
+            Some(Action::Filter) => self.show_filter = true,
+            Some(
+                action @ (Action::Next
+                | Action::Prev
+                | Action::PageDown
+                | Action::PageUp
+                | Action::First
+                | Action::Last),
+            ) if self.focus == Focus::Preview => self.move_preview_lines(action),
+            // Up goes back in the history from the top result, and keeps going back once there.
+            Some(Action::Prev)
+                if self.focus == Focus::Prompt
+                    && (self.history.is_browsing() || !self.results_manager.can_select_prev()) =>
+            {
+                let current = self.history_entry();
+                if let Some(entry) = self.history.older(current).cloned() {
+                    self.apply_search(entry);
+                }
+            }
+            Some(Action::Next) if self.focus == Focus::Prompt && self.history.is_browsing() => {
+                if let Some(entry) = self.history.newer() {
+                    self.apply_search(entry);
+                }
+            }
+            Some(Action::Next) => self.results_manager.next()?,
+            Some(Action::Prev) => self.results_manager.prev()?,
+            Some(Action::NewLine) => {
+                if self.results_manager.is_multiline() {
+                    self.prompt.insert_newline();
+                    self.update_prompt();
+                }
+            }
+            Some(Action::PageDown) => self.results_manager.move_selection(page)?,
+            Some(Action::PageUp) => self.results_manager.move_selection(-page)?,
+            Some(Action::First) => self.jump_to(1)?,
+            Some(Action::Last) => self.results_manager.select_last()?,
+            Some(Action::NewTab) => self.new_tab(),
+            Some(Action::CloseTab) => self.close_tab()?,
+            Some(Action::NextTab) => {
+                self.switch_tab((self.current_tab + 1) % self.tabs.len())?;
+            }
+            Some(Action::PrevTab) => {
+                let count = self.tabs.len();
+                self.switch_tab((self.current_tab + count - 1) % count)?;
+            }
+            Some(Action::ScopeFile) => self.push_scope(ScopeKind::File),
+            Some(Action::ScopeDirectory) => self.push_scope(ScopeKind::Directory),
+            Some(Action::ExcludeFile) => self.push_scope(ScopeKind::ExcludeFile),
+            Some(Action::ExcludeDirectory) => self.push_scope(ScopeKind::ExcludeDirectory),
+            Some(Action::PopScope) => {
+                self.results_manager.pop_scope();
+            }
+            Some(Action::DrillDown) => self.drill_down()?,
+            Some(Action::Back) => self.go_back()?,
+            Some(Action::NextFile) => self.results_manager.next_file()?,
+            Some(Action::PrevFile) => self.results_manager.prev_file()?,
+            Some(Action::ToggleFocus) => self.set_focus(match self.focus {
+                Focus::Prompt => Focus::List,
+                Focus::List if self.results_manager.has_preview() => Focus::Preview,
+                Focus::List | Focus::Preview => Focus::Prompt,
+            }),
+            // Enter after a number jumps to that result instead of opening the selected one.
+            Some(Action::Open) => match count {
+                Some(n) => self.jump_to(n)?,
+                None => self.open_selection(),
+            },
+            Some(Action::VimNext) => self
+                .results_manager
+                .move_selection(count.unwrap_or(1) as isize)?,
+            Some(Action::VimPrev) => self
+                .results_manager
+                .move_selection(-(count.unwrap_or(1) as isize))?,
+            Some(Action::VimFirst) if pending_g => self.jump_to(count.unwrap_or(1))?,
+            Some(Action::VimFirst) => {
+                self.pending_g = true;
+                self.count = count;
+            }
+            Some(Action::VimLast) => match count {
+                Some(n) => self.jump_to(n)?,
+                None => self.results_manager.select_last()?,
+            },
+            Some(Action::HalfPageDown) => self.results_manager.move_selection(half_page)?,
+            Some(Action::HalfPageUp) => self.results_manager.move_selection(-half_page)?,
+            Some(Action::FocusPrompt) => self.set_focus(Focus::Prompt),
+            Some(Action::FocusList) => self.set_focus(Focus::List),
+            Some(Action::CursorLeft) => self.move_preview_cursor(0, -1),
+            Some(Action::CursorRight) => self.move_preview_cursor(0, 1),
+            _ if self.focus == Focus::List => self.count_key(input, count),
+            _ if self.focus == Focus::Preview => (),
+            _ if matches!(input.key, Key::Esc) => (),
+            _ => {
                 if self.prompt.input(input) {
-                    self.results_manager
-                        .set_prompt(self.prompt.lines()[0].clone())
+                    self.history.stop_browsing();
+                    self.update_prompt();
                 }
             }
         };
@@ -158,30 +659,544 @@ impl<'a> App<'a> {
         Ok(())
     }
 
-    fn glob_mode(&mut self) -> io::Result<()> {
-        match event::read()?.into() {
-            Input { key: Key::Esc, .. }
-            | Input {
-                key: Key::Char('c'),
-                ctrl: true,
-                ..
-            }
-            | Input {
-                key: Key::Char('g'),
-                ctrl: true,
-                ..
-            }
-            | Input {
-                key: Key::Enter, ..
-            }
-            | Input {
-                key: Key::Char('m'),
-                ctrl: true,
-                ..
-            } => self.show_glob = false,
-            input => {
-                if self.glob.input(input) {
-                    self.results_manager.set_glob(self.glob.lines()[0].clone())
+    fn help_mode(&mut self, input: Input) {
+        let actions = [
+            Action::Close,
+            Action::Help,
+            Action::Next,
+            Action::Prev,
+            Action::PageDown,
+            Action::PageUp,
+        ];
+        match self.keymap.action(&input, &actions) {
+            Some(Action::Next) => self.help_scroll = self.help_scroll.saturating_add(1),
+            Some(Action::Prev) => self.help_scroll = self.help_scroll.saturating_sub(1),
+            Some(Action::PageDown) => self.help_scroll = self.help_scroll.saturating_add(10),
+            Some(Action::PageUp) => self.help_scroll = self.help_scroll.saturating_sub(10),
+            Some(_) => self.show_help = false,
+            None => (),
+        }
+    }
+
+    fn history_mode(&mut self, input: Input) -> io::Result<()> {
+        let actions = [
+            Action::Close,
+            Action::History,
+            Action::Accept,
+            Action::Next,
+            Action::Prev,
+        ];
+        match self.keymap.action(&input, &actions) {
+            Some(Action::Close | Action::History) => self.show_history = false,
+            Some(Action::Accept) => {
+                let matches = self.history.search(self.history_picker.pattern());
+                if let Some(entry) = matches.get(self.history_picker.selection()).copied() {
+                    let entry = entry.clone();
+                    self.history.stop_browsing();
+                    self.apply_search(entry);
+                }
+                self.show_history = false;
+            }
+            Some(Action::Next) => self.history_picker.next(),
+            Some(Action::Prev) => self.history_picker.prev(),
+            _ => self.history_picker.input(input),
+        };
+
+        Ok(())
+    }
+
+    fn presets_mode(&mut self, input: Input) -> io::Result<()> {
+        let actions = [
+            Action::Close,
+            Action::Presets,
+            Action::Accept,
+            Action::SavePreset,
+            Action::Next,
+            Action::Prev,
+        ];
+        match self.keymap.action(&input, &actions) {
+            Some(Action::Close | Action::Presets) => self.show_presets = false,
+            Some(Action::Accept) => {
+                let matches = self.presets.search(self.presets_picker.pattern());
+                if let Some(preset) = matches.get(self.presets_picker.selection()).copied() {
+                    let entry = preset.search.clone();
+                    self.apply_search(entry);
+                }
+                self.show_presets = false;
+            }
+            Some(Action::SavePreset) => {
+                let name = self.presets_picker.pattern().trim().to_string();
+                if name.is_empty() {
+                    self.presets_picker.message = Some("Type a name first".to_string());
+                    return Ok(());
+                }
+                let preset = presets::Preset {
+                    name,
+                    search: self.history_entry(),
+                };
+                match self.presets.save(preset) {
+                    Ok(_) => self.show_presets = false,
+                    Err(e) => self.presets_picker.message = Some(format!("Could not save: {}", e)),
+                }
+            }
+            Some(Action::Next) => self.presets_picker.next(),
+            Some(Action::Prev) => self.presets_picker.prev(),
+            _ => self.presets_picker.input(input),
+        };
+
+        Ok(())
+    }
+
+    /// Accepting cycles the selected type between searched (`-t`), skipped (`-T`) and neither.
+    fn types_mode(&mut self, input: Input) {
+        let actions = [
+            Action::Close,
+            Action::FileTypes,
+            Action::Accept,
+            Action::Next,
+            Action::Prev,
+        ];
+        match self.keymap.action(&input, &actions) {
+            Some(Action::Close | Action::FileTypes) => self.show_types = false,
+            Some(Action::Accept) => {
+                let matches = self.matching_file_types();
+                let Some(name) = matches.get(self.types_picker.selection()) else {
+                    return;
+                };
+                let name = name.name.clone();
+                let (types, types_not) = self.results_manager.file_types();
+                let (mut types, mut types_not) = (types.to_vec(), types_not.to_vec());
+                if let Some(i) = types.iter().position(|t| *t == name) {
+                    types.remove(i);
+                    types_not.push(name);
+                } else if let Some(i) = types_not.iter().position(|t| *t == name) {
+                    types_not.remove(i);
+                } else {
+                    types.push(name);
+                }
+                self.results_manager.set_file_types(types, types_not);
+            }
+            Some(Action::Next) => self.types_picker.next(),
+            Some(Action::Prev) => self.types_picker.prev(),
+            _ => self.types_picker.input(input),
+        };
+    }
+
+    /// The file types whose name fuzzy matches the search of the types popup, best match first.
+    fn matching_file_types(&self) -> Vec<&FileType> {
+        let pattern = self.types_picker.pattern();
+        let matcher = SkimMatcherV2::default();
+        let mut matches: Vec<(i64, &FileType)> = self
+            .file_types
+            .iter()
+            .filter_map(|t| Some((matcher.fuzzy_match(&t.name, pattern)?, t)))
+            .collect();
+        if !pattern.is_empty() {
+            matches.sort_by_key(|(score, _)| -score);
+        }
+        matches.into_iter().map(|(_, t)| t).collect()
+    }
+
+    /// Digits in the results list make up a number, to jump to that result or to repeat a move.
+    fn count_key(&mut self, input: Input, count: Option<usize>) {
+        if let Input {
+            key: Key::Char(c @ '0'..='9'),
+            ctrl: false,
+            alt: false,
+        } = input
+        {
+            if count.is_some() || c != '0' {
+                let digit = c.to_digit(10).unwrap_or(0) as usize;
+                self.count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+            }
+        }
+    }
+
+    /// Selects the `n`th result, counting from 1, or the last one if there are fewer.
+    fn jump_to(&mut self, n: usize) -> io::Result<()> {
+        if !self.results_manager.select_at(n.saturating_sub(1))? {
+            self.results_manager.select_last()?;
+        }
+        Ok(())
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        self.focus = focus;
+        self.count = None;
+        self.pending_g = false;
+        // The prompt only shows its cursor while it has the focus.
+        self.prompt.set_cursor_style(match focus {
+            Focus::Prompt => Style::default().add_modifier(Modifier::REVERSED),
+            Focus::List | Focus::Preview => Style::default(),
+        });
+    }
+
+    /// Moves the cursor of the focused preview a line, a page or to the first or last line.
+    fn move_preview_lines(&mut self, action: Action) {
+        let Some(preview) = self.areas.preview else {
+            return;
+        };
+        let page = preview.height.saturating_sub(2).max(1) as i32;
+        let lines = match action {
+            Action::Next => 1,
+            Action::Prev => -1,
+            Action::PageDown => page,
+            Action::PageUp => -page,
+            Action::First => i32::MIN / 2,
+            _ => i32::MAX / 2,
+        };
+        self.move_preview_cursor(lines, 0);
+    }
+
+    fn move_preview_cursor(&mut self, lines: i32, columns: i32) {
+        if let Some(preview) = self.areas.preview {
+            self.results_manager
+                .move_preview_cursor(lines, columns, preview.height.into());
+        }
+    }
+
+    /// Searches the identifier at the selected match, or at the cursor of the focused preview,
+    /// the current search going on the trail.
+    fn drill_down(&mut self) -> io::Result<()> {
+        let identifier = match self.focus {
+            Focus::Preview => self.results_manager.preview_identifier(),
+            _ => self.results_manager.selected_identifier(),
+        };
+        let Some(identifier) = identifier else {
+            return Ok(());
+        };
+        self.save_to_history();
+        self.trail.push(TrailEntry {
+            entry: self.history_entry(),
+            filter: self.filter.lines()[0].clone(),
+            focus: self.focus,
+            search: self.results_manager.save_search(),
+        });
+
+        Self::set_text(&mut self.filter, "");
+        self.results_manager.set_filter(String::new())?;
+        self.results_manager.set_mode(Mode::Content);
+        Self::set_text(&mut self.prompt, &format!("\\b{}\\b", identifier));
+        self.update_prompt();
+        // The new search has no preview yet.
+        if self.focus == Focus::Preview {
+            self.set_focus(Focus::List);
+        }
+        Ok(())
+    }
+
+    /// Shows the search drilled down from, as it was.
+    fn go_back(&mut self) -> io::Result<()> {
+        let Some(trail) = self.trail.pop() else {
+            return Ok(());
+        };
+        self.history.stop_browsing();
+        Self::set_text(&mut self.prompt, &trail.entry.prompt);
+        self.globs.set(&trail.entry.glob);
+        Self::set_text(&mut self.filter, &trail.filter);
+        self.results_manager.restore_search(trail.search)?;
+        self.set_focus(trail.focus);
+        Ok(())
+    }
+
+    fn push_scope(&mut self, kind: ScopeKind) {
+        self.save_to_history();
+        self.results_manager.push_scope(kind);
+    }
+
+    /// Puts the shown search aside, to switch to another tab.
+    fn save_tab(&mut self) -> Tab<'a> {
+        self.history.stop_browsing();
+        Tab {
+            entry: self.history_entry(),
+            filter: self.filter.lines()[0].clone(),
+            focus: self.focus,
+            trail: std::mem::take(&mut self.trail),
+            search: self.results_manager.save_search(),
+        }
+    }
+
+    fn load_tab(&mut self, tab: Tab<'a>) -> io::Result<()> {
+        Self::set_text(&mut self.prompt, &tab.entry.prompt);
+        self.globs.set(&tab.entry.glob);
+        Self::set_text(&mut self.filter, &tab.filter);
+        self.trail = tab.trail;
+        self.results_manager.restore_search(tab.search)?;
+        self.set_focus(tab.focus);
+        Ok(())
+    }
+
+    fn switch_tab(&mut self, index: usize) -> io::Result<()> {
+        if index == self.current_tab || index >= self.tabs.len() {
+            return Ok(());
+        }
+        let Some(tab) = self.tabs[index].take() else {
+            return Ok(());
+        };
+        self.tabs[self.current_tab] = Some(self.save_tab());
+        self.current_tab = index;
+        self.load_tab(tab)
+    }
+
+    /// Opens an empty search after the current tab, keeping the current glob and options.
+    fn new_tab(&mut self) {
+        self.save_to_history();
+        let tab = self.save_tab();
+        self.tabs[self.current_tab] = Some(tab);
+        self.current_tab += 1;
+        self.tabs.insert(self.current_tab, None);
+
+        Self::set_text(&mut self.prompt, "");
+        Self::set_text(&mut self.filter, "");
+        self.set_focus(Focus::Prompt);
+        self.update_prompt();
+    }
+
+    /// Closes the current tab and shows the next one, or the previous one if it was the last.
+    fn close_tab(&mut self) -> io::Result<()> {
+        if self.tabs.len() == 1 {
+            return Ok(());
+        }
+        self.save_to_history();
+        self.tabs.remove(self.current_tab);
+        self.current_tab = self.current_tab.min(self.tabs.len() - 1);
+        let Some(tab) = self.tabs[self.current_tab].take() else {
+            return Ok(());
+        };
+        for entry in std::mem::take(&mut self.trail) {
+            results::Manager::discard_search(entry.search)?;
+        }
+        self.load_tab(tab)
+    }
+
+    fn open_selection(&mut self) {
+        self.save_to_history();
+        self.should_restart_terminal = self.results_manager.open_selection();
+    }
+
+    /// Clicks select results and toggle the checkboxes, the wheel scrolls the pane under the
+    /// cursor and dragging the divider resizes the preview.
+    fn mouse_mode(&mut self, mouse: MouseEvent) -> io::Result<()> {
+        let (column, row) = (mouse.column, mouse.row);
+        let contains = |area: Rect| {
+            area.x <= column && column < area.right() && area.y <= row && row < area.bottom()
+        };
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some(index) = self.areas.tabs.iter().position(|&tab| contains(tab)) {
+                    self.switch_tab(index)?;
+                } else if self.is_on_divider(column, row) {
+                    self.dragging_split = true;
+                } else if contains(self.areas.hidden_box) {
+                    self.results_manager.toggle_hidden();
+                } else if contains(self.areas.multiline_box) {
+                    self.results_manager.toggle_multiline();
+                    self.update_prompt();
+                } else if contains(self.areas.watch_box) {
+                    self.results_manager.toggle_watch();
+                } else if contains(self.areas.results) {
+                    self.click_result(row)?;
+                } else if let Some(preview) = self.areas.preview.filter(|&area| contains(area)) {
+                    self.click_preview(preview, column, row);
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.dragging_split => {
+                let body = self.areas.body;
+                let (position, start, size) = match self.layout.preview_position() {
+                    PreviewPosition::Right => (column, body.x, body.width),
+                    PreviewPosition::Bottom => (row, body.y, body.height),
+                };
+                let split = (position.saturating_sub(start) as u32 * 100 / size.max(1) as u32)
+                    .clamp(10, 90);
+                self.layout.preview_split = Some(split as u16);
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.dragging_split = false,
+            MouseEventKind::ScrollDown if contains(self.areas.results) => {
+                self.results_manager.next()?
+            }
+            MouseEventKind::ScrollUp if contains(self.areas.results) => {
+                self.results_manager.prev()?
+            }
+            MouseEventKind::ScrollDown if self.areas.preview.is_some_and(contains) => {
+                self.scroll_preview(3)
+            }
+            MouseEventKind::ScrollUp if self.areas.preview.is_some_and(contains) => {
+                self.scroll_preview(-3)
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Focuses the preview, with its cursor on the clicked character.
+    fn click_preview(&mut self, preview: Rect, column: u16, row: u16) {
+        if row <= preview.y || row + 1 >= preview.bottom() || !self.results_manager.has_preview() {
+            return;
+        }
+        self.results_manager.click_preview(
+            row - preview.y - 1,
+            column.saturating_sub(preview.x + 1),
+            preview.height.into(),
+        );
+        self.set_focus(Focus::Preview);
+    }
+
+    fn scroll_preview(&mut self, lines: i32) {
+        if let Some(preview) = self.areas.preview {
+            self.results_manager
+                .scroll_preview(lines, preview.height.into());
+        }
+    }
+
+    /// Whether `column` and `row` are on the borders between the results and the preview.
+    fn is_on_divider(&self, column: u16, row: u16) -> bool {
+        let (Some(preview), results) = (self.areas.preview, self.areas.results) else {
+            return false;
+        };
+        match self.layout.preview_position() {
+            PreviewPosition::Right => {
+                (results.right().saturating_sub(1)..=preview.x).contains(&column)
+                    && (preview.y..preview.bottom()).contains(&row)
+            }
+            PreviewPosition::Bottom => {
+                (results.bottom().saturating_sub(1)..=preview.y).contains(&row)
+                    && (preview.x..preview.right()).contains(&column)
+            }
+        }
+    }
+
+    /// Selects the result on `row`, opening it if it was just clicked.
+    fn click_result(&mut self, row: u16) -> io::Result<()> {
+        let results = self.areas.results;
+        if row <= results.y || row + 1 >= results.bottom() {
+            return Ok(());
+        }
+        let index = self.areas.results_offset + (row - results.y - 1) as usize;
+        if !self.results_manager.select_at(index)? {
+            return Ok(());
+        }
+        match self.last_click.take() {
+            Some((time, clicked)) if clicked == index && time.elapsed() < DOUBLE_CLICK => {
+                self.open_selection()
+            }
+            _ => self.last_click = Some((Instant::now(), index)),
+        }
+        Ok(())
+    }
+
+    /// The current search, as it would be recorded in the history.
+    fn history_entry(&self) -> history::Entry {
+        history::Entry {
+            prompt: self.prompt.lines().join("\n"),
+            glob: self.globs.glob(),
+            roots: self.results_manager.roots().to_vec(),
+            mode: self.results_manager.mode(),
+            show_hidden: self.results_manager.is_showing_hidden(),
+            multiline: self.results_manager.is_multiline(),
+            types: self.results_manager.file_types().0.to_vec(),
+            types_not: self.results_manager.file_types().1.to_vec(),
+        }
+    }
+
+    fn save_to_history(&mut self) {
+        // Failing to write the history file shouldn't get in the way of searching.
+        let _ = self.history.add(self.history_entry());
+    }
+
+    /// Runs a search from the history or a preset.
+    fn apply_search(&mut self, entry: history::Entry) {
+        Self::set_text(&mut self.prompt, &entry.prompt);
+        self.globs.set(&entry.glob);
+        self.results_manager.set_roots(entry.roots);
+        self.results_manager.set_mode(entry.mode);
+        self.results_manager.set_hidden(entry.show_hidden);
+        self.results_manager.set_multiline(entry.multiline);
+        self.results_manager.set_glob(entry.glob);
+        self.results_manager
+            .set_file_types(entry.types, entry.types_not);
+        self.update_prompt();
+    }
+
+    /// Replaces the content of `textarea`, keeping its block and placeholder.
+    fn set_text(textarea: &mut TextArea, text: &str) {
+        textarea.move_cursor(CursorMove::Bottom);
+        textarea.move_cursor(CursorMove::End);
+        while textarea.delete_char() {}
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                textarea.insert_newline();
+            }
+            textarea.insert_str(line);
+        }
+    }
+
+    /// Sends the prompt to the manager, only its first line unless searching multiline.
+    fn update_prompt(&mut self) {
+        let prompt = if self.results_manager.is_multiline() {
+            self.prompt.lines().join("\n")
+        } else {
+            self.prompt.lines()[0].clone()
+        };
+        self.results_manager.set_prompt(prompt);
+    }
+
+    fn glob_mode(&mut self, input: Input) -> io::Result<()> {
+        let actions = [
+            Action::Close,
+            Action::EditGlob,
+            Action::AddGlob,
+            Action::ToggleGlob,
+            Action::CompleteGlob,
+            Action::Next,
+            Action::Prev,
+        ];
+        let changed = match self.keymap.action(&input, &actions) {
+            Some(Action::AddGlob) => {
+                self.globs.add();
+                false
+            }
+            Some(Action::ToggleGlob) => {
+                self.globs.toggle();
+                true
+            }
+            Some(Action::CompleteGlob) => self.globs.complete(),
+            Some(Action::Next) => {
+                self.globs.next();
+                false
+            }
+            Some(Action::Prev) => {
+                self.globs.prev();
+                false
+            }
+            Some(_) => {
+                self.show_glob = false;
+                false
+            }
+            None => self.globs.input(input),
+        };
+        if changed {
+            self.results_manager.set_glob(self.globs.glob());
+        }
+
+        Ok(())
+    }
+
+    fn filter_mode(&mut self, input: Input) -> io::Result<()> {
+        let actions = [
+            Action::Close,
+            Action::Filter,
+            Action::Accept,
+            Action::Next,
+            Action::Prev,
+        ];
+        match self.keymap.action(&input, &actions) {
+            Some(Action::Next) => self.results_manager.next()?,
+            Some(Action::Prev) => self.results_manager.prev()?,
+            Some(_) => self.show_filter = false,
+            None => {
+                if self.filter.input(input) {
+                    self.results_manager
+                        .set_filter(self.filter.lines()[0].clone())?
                 }
             }
         };
@@ -190,78 +1205,479 @@ impl<'a> App<'a> {
     }
 
     fn ui(&mut self, frame: &mut Frame) {
+        let filter_height = if self.show_filter || !self.filter.lines()[0].is_empty() {
+            3
+        } else {
+            0
+        };
+        // The prompt grows with its lines when searching multiline, up to a few lines.
+        let prompt_height = if self.results_manager.is_multiline() {
+            self.prompt.lines().len().clamp(1, 6) as u16 + 2
+        } else {
+            3
+        };
+        let tab_bar_height = if self.tabs.len() > 1 { 1 } else { 0 };
         let main_layout = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
-                Constraint::Length(3),
+                Constraint::Length(tab_bar_height),
+                Constraint::Length(prompt_height),
+                Constraint::Length(filter_height),
                 Constraint::Min(1),
+                Constraint::Length(1),
                 Constraint::Length(3),
             ])
             .split(frame.size());
 
         let top_line = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints([Constraint::Min(1), Constraint::Length(20)])
-            .split(main_layout[0]);
+            .constraints([
+                Constraint::Min(1),
+                Constraint::Length(20),
+                Constraint::Length(16),
+                Constraint::Length(12),
+            ])
+            .split(main_layout[1]);
 
+        self.render_tab_bar(frame, main_layout[0]);
+        let (types, types_not) = self.results_manager.file_types();
+        let title = match Self::types_label(types, types_not) {
+            types if types.is_empty() => format!(" {} ", self.results_manager.mode().name()),
+            types => format!(" {} · {} ", self.results_manager.mode().name(), types),
+        };
+        self.prompt
+            .set_block(Self::default_block(&self.theme).title(title));
         frame.render_widget(self.prompt.widget(), top_line[0]);
         let s = if self.results_manager.is_showing_hidden() {
             "🗹 Show hidden"
         } else {
             "☐ Show hidden"
         };
-        frame.render_widget(Paragraph::new(s).block(Self::default_block()), top_line[1]);
+        frame.render_widget(
+            Paragraph::new(s).block(Self::default_block(&self.theme)),
+            top_line[1],
+        );
+        let s = if self.results_manager.is_multiline() {
+            "🗹 Multiline"
+        } else {
+            "☐ Multiline"
+        };
+        frame.render_widget(
+            Paragraph::new(s).block(Self::default_block(&self.theme)),
+            top_line[2],
+        );
+        let s = if self.results_manager.is_watching() {
+            "🗹 Watch"
+        } else {
+            "☐ Watch"
+        };
+        frame.render_widget(
+            Paragraph::new(s).block(Self::default_block(&self.theme)),
+            top_line[3],
+        );
+        frame.render_widget(self.filter.widget(), main_layout[2]);
+        self.areas.hidden_box = top_line[1];
+        self.areas.multiline_box = top_line[2];
+        self.areas.watch_box = top_line[3];
+        self.areas.body = main_layout[3];
+        self.areas.preview = None;
 
         let results_layout = if self.results_manager.show_preview {
+            let split = self.layout.preview_split();
             let body = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-                .split(main_layout[1]);
+                .direction(match self.layout.preview_position() {
+                    PreviewPosition::Right => Direction::Horizontal,
+                    PreviewPosition::Bottom => Direction::Vertical,
+                })
+                .constraints([
+                    Constraint::Percentage(split),
+                    Constraint::Percentage(100 - split),
+                ])
+                .split(main_layout[3]);
 
+            let title = match self.focus {
+                Focus::Preview => " Preview · cursor ",
+                _ => " Preview ",
+            };
             frame.render_widget(
                 self.results_manager
-                    .get_preview(body[0].height.into())
-                    .block(Self::default_block().title(" Preview ")),
+                    .get_preview(body[1].height.into())
+                    .block(Self::default_block(&self.theme).title(title)),
                 body[1],
             );
+            if self.focus == Focus::Preview {
+                let cursor = self.results_manager.preview_cursor(body[1].height.into());
+                if let Some((x, y)) = cursor {
+                    let (x, y) = (body[1].x + 1 + x, body[1].y + 1 + y);
+                    if x + 1 < body[1].right() {
+                        frame
+                            .buffer_mut()
+                            .get_mut(x, y)
+                            .set_style(Style::default().add_modifier(Modifier::REVERSED));
+                    }
+                }
+            }
 
+            self.areas.preview = Some(body[1]);
             body[0]
         } else {
-            main_layout[1]
+            main_layout[3]
         };
+        let mut list_state = self.results_manager.get_list_state();
         frame.render_stateful_widget(
             self.results_manager
                 .get_list()
-                .block(Self::default_block().title(" Results "))
+                .block(Self::default_block(&self.theme).title(self.results_title()))
+                .highlight_style(self.theme.selection)
                 .highlight_symbol("»"),
             results_layout,
-            &mut self.results_manager.get_list_state(),
+            &mut list_state,
         );
+        self.areas.results = results_layout;
+        self.areas.results_offset = list_state.offset();
 
-        let line = Line::from(vec![
-            Span::styled("↑↓", Style::default().fg(Color::Red)),
-            Span::raw(": Navigate results "),
-            Span::styled("ENTER", Style::default().fg(Color::Red)),
-            Span::raw(": Open file "),
-            Span::styled("<C+g>", Style::default().fg(Color::Red)),
-            Span::raw(": Edit glob "),
-            Span::styled("<C+p>", Style::default().fg(Color::Red)),
-            Span::raw(": Toggle preview "),
-            Span::styled("<C+h>", Style::default().fg(Color::Red)),
-            Span::raw(": Toggle search in hidden files "),
-            Span::styled("<C+c>", Style::default().fg(Color::Red)),
-            Span::raw(": Quit "),
-        ]);
+        frame.render_widget(Paragraph::new(self.status_line()), main_layout[4]);
+        let footer = self.footer(main_layout[5].width.saturating_sub(2).into());
         frame.render_widget(
-            Paragraph::new(Text::from(line)).block(Self::default_block()),
-            main_layout[2],
+            Paragraph::new(footer).block(Self::default_block(&self.theme)),
+            main_layout[5],
         );
 
         if self.show_glob {
-            let popup_area = App::centered_rect(50, 5, frame.size());
-            frame.render_widget(Clear, popup_area);
-            frame.render_widget(self.glob.widget(), popup_area);
+            let area = App::centered_rect(50, 50, frame.size());
+            let height = self.globs.height().min(area.height);
+            let area = Rect { height, ..area };
+            let block = Self::default_block(&self.theme)
+                .title(" Globs ")
+                .title_alignment(Alignment::Center);
+            self.globs.render(frame, area, block, &self.theme);
+        }
+
+        if self.show_history {
+            self.render_history(frame);
+        }
+        if self.show_presets {
+            self.render_presets(frame);
         }
+        if self.show_types {
+            self.render_types(frame);
+        }
+        if self.show_help {
+            self.render_help(frame);
+        }
+    }
+
+    /// The tabs numbered and named after their prompt, the current one reversed.
+    fn render_tab_bar(&mut self, frame: &mut Frame, area: Rect) {
+        self.areas.tabs.clear();
+        if self.tabs.len() == 1 {
+            return;
+        }
+        let mut spans = Vec::new();
+        let mut x = area.x;
+        for (i, tab) in self.tabs.iter().enumerate() {
+            let prompt = match tab {
+                Some(tab) => &tab.entry.prompt,
+                None => &self.prompt.lines()[0],
+            };
+            let mut name: String = prompt
+                .lines()
+                .next()
+                .unwrap_or("")
+                .chars()
+                .take(20)
+                .collect();
+            if name.is_empty() {
+                name = "New search".to_string();
+            }
+            let span = if tab.is_none() {
+                Span::styled(
+                    format!(" {}: {} ", i + 1, name),
+                    Style::default().add_modifier(Modifier::REVERSED),
+                )
+            } else {
+                Span::styled(format!(" {}: {} ", i + 1, name), self.theme.muted)
+            };
+            let width = (span.width() as u16).min(area.right().saturating_sub(x));
+            self.areas.tabs.push(Rect::new(x, area.y, width, 1));
+            x += width;
+            spans.extend([span, Span::raw("│")]);
+            x = (x + 1).min(area.right());
+        }
+        frame.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+
+    /// Whether the search is still running, what it found so far and where the selection is.
+    fn status_line(&self) -> Line<'static> {
+        let Some(status) = self.results_manager.status() else {
+            return Line::from(match self.results_manager.watch_error() {
+                Some(error) => Span::styled(format!(" ✗ {}", error), self.theme.key),
+                None => Span::styled(" Type to search", self.theme.muted),
+            });
+        };
+
+        let mut spans = vec![if status.running {
+            let frame = status.elapsed.as_millis() / SPINNER_INTERVAL.as_millis();
+            let spinner = SPINNER[frame as usize % SPINNER.len()];
+            Span::styled(format!(" {} Searching", spinner), self.theme.key)
+        } else if let Some(error) = &status.error {
+            Span::styled(format!(" ✗ {}", error), self.theme.key)
+        } else {
+            Span::styled(" ✓ Done", self.theme.line_number)
+        }];
+        let found = match (status.results, self.results_manager.mode()) {
+            (0, _) => "no results".to_string(),
+            (n, Mode::Content) => format!(
+                "{} {} in {} {}",
+                n,
+                if n == 1 { "match" } else { "matches" },
+                status.files,
+                if status.files == 1 { "file" } else { "files" }
+            ),
+            (n, _) => format!("{} {}", n, if n == 1 { "file" } else { "files" }),
+        };
+        let mut parts = vec![found, format_duration(status.elapsed)];
+        if let Some(summary) = status.summary {
+            parts.push(format!(
+                "searched {} files ({})",
+                summary.files_searched,
+                format_bytes(summary.bytes_searched)
+            ));
+        }
+        if let Some(index) = status.selection {
+            let mut selection = format!("{} of {}", index + 1, status.shown);
+            if status.shown != status.results {
+                selection.push_str(&format!(" ({} before filtering)", status.results));
+            }
+            parts.push(selection);
+        }
+        if !self.trail.is_empty() {
+            let back = self.keymap.label(&[Action::Back]);
+            parts.push(format!("{}: back ({})", back, self.trail.len()));
+        }
+        for part in parts {
+            spans.push(Span::styled(" · ", self.theme.muted));
+            spans.push(Span::raw(part));
+        }
+        Line::from(spans)
+    }
+
+    /// The hints for what's on screen, as many as fit in `width`.
+    fn footer(&self, width: usize) -> Line<'static> {
+        let hints = if self.show_help {
+            HELP_FOOTER
+        } else if self.show_history {
+            HISTORY_FOOTER
+        } else if self.show_presets {
+            PRESETS_FOOTER
+        } else if self.show_types {
+            TYPES_FOOTER
+        } else if self.show_glob {
+            GLOB_FOOTER
+        } else if self.show_filter {
+            FILTER_FOOTER
+        } else if self.focus == Focus::List {
+            LIST_FOOTER
+        } else if self.focus == Focus::Preview {
+            PREVIEW_FOOTER
+        } else if self.results_manager.is_multiline() {
+            MULTILINE_FOOTER
+        } else {
+            PROMPT_FOOTER
+        };
+
+        let mut spans = Vec::new();
+        let mut used = 0;
+        for (actions, description) in hints {
+            let key = Span::styled(self.keymap.label(actions), self.theme.key);
+            let description = Span::raw(format!(": {} ", description));
+            if key.content.is_empty() {
+                continue;
+            }
+            used += key.width() + description.width();
+            if used > width {
+                break;
+            }
+            spans.extend([key, description]);
+        }
+        Line::from(spans)
+    }
+
+    /// Lists every action with all of its keys, as currently bound.
+    fn render_help(&mut self, frame: &mut Frame) {
+        let actions: Vec<Action> = Action::ALL
+            .iter()
+            .copied()
+            .filter(|action| self.vim || !Action::VIM.contains(action))
+            .collect();
+        let count_keys = format!("N {}", self.help_keys(Action::Open));
+        let key_width = actions
+            .iter()
+            .map(|&action| self.help_keys(action).chars().count())
+            .chain([count_keys.chars().count()])
+            .max()
+            .unwrap_or(0);
+        let mut lines: Vec<Line> = actions
+            .iter()
+            .map(|&action| {
+                Line::from(vec![
+                    Span::styled(
+                        format!(" {:width$}  ", self.help_keys(action), width = key_width),
+                        self.theme.key,
+                    ),
+                    Span::raw(action.description()),
+                ])
+            })
+            .collect();
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            " In the results list",
+            self.theme.muted,
+        )));
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!(" {:width$}  ", count_keys, width = key_width),
+                self.theme.key,
+            ),
+            Span::raw("Select the Nth result"),
+        ]));
+
+        let area = App::centered_rect(70, 80, frame.size());
+        let max_scroll = (lines.len() as u16).saturating_sub(area.height.saturating_sub(2));
+        self.help_scroll = self.help_scroll.min(max_scroll);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines).scroll((self.help_scroll, 0)).block(
+                Self::default_block(&self.theme)
+                    .title(" Help ")
+                    .title_alignment(Alignment::Center),
+            ),
+            area,
+        );
+    }
+
+    fn help_keys(&self, action: Action) -> String {
+        let keys = self.keymap.keys(action).iter();
+        keys.map(ToString::to_string).collect::<Vec<_>>().join(" ")
+    }
+
+    /// Says whether the list has the focus, then where the search is scoped, as breadcrumbs.
+    fn results_title(&self) -> String {
+        let mut title = match (self.focus, self.count) {
+            (Focus::Prompt | Focus::Preview, _) => " Results ".to_string(),
+            (Focus::List, None) => " Results · list ".to_string(),
+            (Focus::List, Some(n)) => format!(" Results · list · go to {} ", n),
+        };
+        for scope in self.results_manager.scopes() {
+            title.push_str(&format!("› {} ", scope));
+        }
+        title
+    }
+
+    fn render_history(&mut self, frame: &mut Frame) {
+        let items = self
+            .history
+            .search(self.history_picker.pattern())
+            .into_iter()
+            .map(|entry| ListItem::new(Self::search_line(&self.theme, entry, Vec::new())))
+            .collect();
+        self.history_picker.render(
+            frame,
+            App::centered_rect(60, 60, frame.size()),
+            items,
+            Self::default_block(&self.theme),
+        );
+    }
+
+    fn render_presets(&mut self, frame: &mut Frame) {
+        let items = self
+            .presets
+            .search(self.presets_picker.pattern())
+            .into_iter()
+            .map(|preset| {
+                let name = Span::styled(
+                    format!("{}  ", preset.name),
+                    Style::default().add_modifier(Modifier::BOLD),
+                );
+                ListItem::new(Self::search_line(&self.theme, &preset.search, vec![name]))
+            })
+            .collect();
+        self.presets_picker.render(
+            frame,
+            App::centered_rect(60, 60, frame.size()),
+            items,
+            Self::default_block(&self.theme).title(format!(
+                " {}: Save the current search under the typed name ",
+                self.keymap.label(&[Action::SavePreset])
+            )),
+        );
+    }
+
+    fn render_types(&mut self, frame: &mut Frame) {
+        let (types, types_not) = self.results_manager.file_types();
+        let items = self
+            .matching_file_types()
+            .into_iter()
+            .map(|file_type| {
+                let checkbox = if types.contains(&file_type.name) {
+                    "🗹"
+                } else if types_not.contains(&file_type.name) {
+                    "🗷"
+                } else {
+                    "☐"
+                };
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{} {}  ", checkbox, file_type.name)),
+                    Span::styled(file_type.globs.join(", "), self.theme.muted),
+                ]))
+            })
+            .collect();
+        self.types_picker.render(
+            frame,
+            App::centered_rect(60, 60, frame.size()),
+            items,
+            Self::default_block(&self.theme),
+        );
+    }
+
+    /// The picked file types, as in `rust, toml, not js`.
+    fn types_label(types: &[String], types_not: &[String]) -> String {
+        let not = types_not.iter().map(|t| format!("not {}", t));
+        types
+            .iter()
+            .cloned()
+            .chain(not)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// A one line summary of a search: its prompt, glob, roots and mode.
+    fn search_line(
+        theme: &Theme,
+        entry: &history::Entry,
+        mut spans: Vec<Span<'static>>,
+    ) -> Line<'static> {
+        spans.push(Span::raw(entry.prompt.replace('\n', "⏎")));
+        if !entry.glob.is_empty() {
+            spans.push(Span::styled(format!("  {}", entry.glob), theme.path));
+        }
+        if !entry.roots.is_empty() {
+            spans.push(Span::styled(
+                format!("  {}", entry.roots.join(" ")),
+                theme.line_number,
+            ));
+        }
+        if !entry.types.is_empty() || !entry.types_not.is_empty() {
+            spans.push(Span::styled(
+                format!("  {}", Self::types_label(&entry.types, &entry.types_not)),
+                theme.line_number,
+            ));
+        }
+        spans.push(Span::styled(
+            format!("  {}", entry.mode.name()),
+            theme.muted,
+        ));
+        Line::from(spans)
     }
 
     /// helper function from ratatui
@@ -286,7 +1702,54 @@ impl<'a> App<'a> {
     }
 }
 
+fn format_duration(duration: Duration) -> String {
+    if duration.as_secs() == 0 {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
 fn main() -> io::Result<()> {
-    App::new().run()?;
+    let cli = Cli::parse();
+    if let Some(Subcommands::Index) = cli.subcommand {
+        let stats = Index::update(&Index::default_cache()?, Path::new("."))?;
+        println!(
+            "Indexed {} files ({} read) into {}",
+            stats.files,
+            stats.reindexed,
+            stats.path.display()
+        );
+        return Ok(());
+    }
+
+    // Report config mistakes plainly before taking over the terminal.
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("lazyrip: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let theme = Theme::new(&config.theme, cli.monochrome);
+    let mut app = App::new(cli.build_backend(), config, theme)?;
+    if cli.watch && !app.results_manager.is_watching() {
+        app.results_manager.toggle_watch();
+    }
+    app.run()?;
     Ok(())
 }
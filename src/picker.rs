@@ -0,0 +1,74 @@
+use ratatui::{prelude::*, widgets::*};
+use tui_textarea::{Input, TextArea};
+
+/// A popup listing items under a search box, used for the history and the presets.
+pub struct Picker<'a> {
+    search: TextArea<'a>,
+    selection: usize,
+    /// Shown instead of the list's title, e.g. after failing to save.
+    pub message: Option<String>,
+}
+
+impl<'a> Picker<'a> {
+    pub fn new(block: Block<'a>, placeholder: &str) -> Picker<'a> {
+        let mut search = TextArea::default();
+        search.set_placeholder_text(placeholder);
+        search.set_block(block);
+        search.set_cursor_line_style(Style::default());
+        Picker {
+            search,
+            selection: 0,
+            message: None,
+        }
+    }
+
+    pub fn open(&mut self) {
+        self.selection = 0;
+        self.message = None;
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.search.lines()[0]
+    }
+
+    pub fn selection(&self) -> usize {
+        self.selection
+    }
+
+    pub fn next(&mut self) {
+        self.selection += 1;
+    }
+
+    pub fn prev(&mut self) {
+        self.selection = self.selection.saturating_sub(1);
+    }
+
+    /// Edits the search.
+    pub fn input(&mut self, input: Input) {
+        if self.search.input(input) {
+            self.selection = 0;
+            self.message = None;
+        }
+    }
+
+    /// Renders the popup over `area`, `items` being the matches of the current search.
+    pub fn render(&mut self, frame: &mut Frame, area: Rect, items: Vec<ListItem>, block: Block) {
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(1)])
+            .split(area);
+        self.selection = self.selection.min(items.len().saturating_sub(1));
+        let block = match &self.message {
+            Some(message) => block.title(format!(" {} ", message)),
+            None => block,
+        };
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(self.search.widget(), layout[0]);
+        frame.render_stateful_widget(
+            List::new(items).block(block).highlight_symbol("»"),
+            layout[1],
+            &mut ListState::default().with_selected(Some(self.selection)),
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use crate::config::PROJECT_FILE;
+use crate::history::Entry;
+use serde::Deserialize;
+use std::fs;
+use std::io::{Error, Result};
+use std::path::PathBuf;
+use toml_edit::{value, Array, ArrayOfTables, DocumentMut, Item, Table};
+
+/// A named search saved in the project config file, the user's config can have some too:
+///
+/// ```toml
+/// [[preset]]
+/// name = "unsafe blocks"
+/// prompt = "unsafe \\{ -SAFETY:"
+/// glob = "*.rs"
+/// roots = ["src"]
+/// types = ["rust"]
+/// ```
+///
+/// Omitted fields default to an empty glob, a content search and all toggles off.
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "toml::Table")]
+pub struct Preset {
+    pub name: String,
+    pub search: Entry,
+}
+
+/// Splits off the name by hand, serde's `flatten` would let unknown fields through.
+impl TryFrom<toml::Table> for Preset {
+    type Error = String;
+
+    fn try_from(mut table: toml::Table) -> std::result::Result<Preset, String> {
+        let name = match table.remove("name") {
+            Some(toml::Value::String(name)) => name,
+            _ => return Err("preset without a name".to_string()),
+        };
+        let search = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| format!("preset `{}`: {}", name, e))?;
+        Ok(Preset { name, search })
+    }
+}
+
+pub struct Presets {
+    path: PathBuf,
+    presets: Vec<Preset>,
+}
+
+impl Presets {
+    pub fn new(presets: Vec<Preset>) -> Presets {
+        Presets {
+            path: PathBuf::from(PROJECT_FILE),
+            presets,
+        }
+    }
+
+    /// The presets whose name contains `pattern`, ignoring case, in file order.
+    pub fn search(&self, pattern: &str) -> Vec<&Preset> {
+        let pattern = pattern.to_lowercase();
+        self.presets
+            .iter()
+            .filter(|p| p.name.to_lowercase().contains(&pattern))
+            .collect()
+    }
+
+    /// Saves `preset` to the config file, replacing the one with the same name. The rest of the
+    /// file, comments included, is kept as is.
+    pub fn save(&mut self, preset: Preset) -> Result<()> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut document: DocumentMut = content
+            .parse()
+            .map_err(|e| Error::other(format!("{}: {}", PROJECT_FILE, e)))?;
+
+        let tables = document
+            .entry("preset")
+            .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+            .as_array_of_tables_mut()
+            .ok_or_else(|| Error::other(format!("{}: preset is not an array", PROJECT_FILE)))?;
+        let existing = tables
+            .iter()
+            .position(|t| t.get("name").and_then(Item::as_str) == Some(&preset.name[..]));
+        match existing.and_then(|index| tables.get_mut(index)) {
+            Some(table) => *table = Self::to_table(&preset),
+            None => tables.push(Self::to_table(&preset)),
+        }
+        fs::write(&self.path, document.to_string())?;
+
+        match self.presets.iter_mut().find(|p| p.name == preset.name) {
+            Some(existing) => *existing = preset,
+            None => self.presets.push(preset),
+        }
+        Ok(())
+    }
+
+    fn to_table(preset: &Preset) -> Table {
+        let mut table = Table::new();
+        table["name"] = value(&preset.name);
+        table["prompt"] = value(&preset.search.prompt);
+        if !preset.search.glob.is_empty() {
+            table["glob"] = value(&preset.search.glob);
+        }
+        if !preset.search.roots.is_empty() {
+            table["roots"] = value(preset.search.roots.iter().collect::<Array>());
+        }
+        if let Ok(toml::Value::String(mode)) = toml::Value::try_from(preset.search.mode) {
+            table["mode"] = value(mode);
+        }
+        if preset.search.show_hidden {
+            table["show_hidden"] = value(true);
+        }
+        if preset.search.multiline {
+            table["multiline"] = value(true);
+        }
+        if !preset.search.types.is_empty() {
+            table["types"] = value(preset.search.types.iter().collect::<Array>());
+        }
+        if !preset.search.types_not.is_empty() {
+            table["types_not"] = value(preset.search.types_not.iter().collect::<Array>());
+        }
+        table
+    }
+}
@@ -0,0 +1,394 @@
+use super::ripgrep::Options;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use std::io::{BufRead, BufReader, Error, Result};
+use std::ops::Range;
+use std::process::{Child, Command, Stdio};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// A single matching line reported by a search backend.
+///
+/// File name results have a `line_number` of 0 and their path as `text`, followed by the hit counts
+/// in the boolean mode.
+pub struct SearchResult {
+    pub path: String,
+    pub line_number: i32,
+    pub column: usize,
+    pub text: String,
+    /// Byte ranges of the matches inside `text`.
+    pub matches: Vec<Range<usize>>,
+    /// How well the result matched a fuzzy query, higher is better.
+    pub score: i64,
+    /// The matching lines of a file result, all highlighted in its preview.
+    pub hit_lines: Vec<i32>,
+}
+
+impl SearchResult {
+    pub fn file(path: String) -> SearchResult {
+        SearchResult {
+            text: path.clone(),
+            path,
+            line_number: 0,
+            column: 1,
+            matches: Vec::new(),
+            score: 0,
+            hit_lines: Vec::new(),
+        }
+    }
+}
+
+/// Backends are shared with the threads chaining searches, see [`super::boolean`].
+pub trait SearchBackend: Send + Sync {
+    fn search(&self, options: &Options) -> Result<Search>;
+
+    /// Lists the files `search` would look into, without reading them.
+    fn files(&self, options: &Options) -> Result<Search> {
+        super::in_process::list_files(options)
+    }
+}
+
+/// Something that can stop a running search.
+pub trait Cancel: Send {
+    fn cancel(&mut self) -> Result<()>;
+}
+
+impl Cancel for Child {
+    fn cancel(&mut self) -> Result<()> {
+        self.kill()?;
+        // TODO: don't wait here?
+        self.wait()?;
+        Ok(())
+    }
+}
+
+/// Totals of a finished search, for backends that report them, like rg's `summary` message.
+#[derive(Clone, Copy, Default, Deserialize)]
+pub struct Summary {
+    #[serde(rename = "searches")]
+    pub files_searched: u64,
+    pub bytes_searched: u64,
+}
+
+/// A running search: results stream in through `rx` until the backend is done or cancelled.
+pub struct Search {
+    rx: mpsc::Receiver<SearchResult>,
+    canceller: Box<dyn Cancel>,
+    summary: Arc<OnceLock<Summary>>,
+    /// Why the search stopped early, for searches running in the background.
+    error: Arc<OnceLock<String>>,
+}
+
+impl Search {
+    pub fn new(rx: mpsc::Receiver<SearchResult>, canceller: Box<dyn Cancel>) -> Search {
+        Search {
+            rx,
+            canceller,
+            summary: Arc::new(OnceLock::new()),
+            error: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Where the backend puts its summary, before closing the channel of results.
+    pub fn summary_slot(&self) -> Arc<OnceLock<Summary>> {
+        self.summary.clone()
+    }
+
+    pub fn summary(&self) -> Option<Summary> {
+        self.summary.get().copied()
+    }
+
+    /// Where a background search puts the error that stopped it, before closing the channel.
+    pub fn error_slot(&self) -> Arc<OnceLock<String>> {
+        self.error.clone()
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.get().map(String::as_str)
+    }
+
+    /// Spawns `command` and parses its stdout as `format`, one result per line.
+    pub fn spawn(mut command: Command, format: OutputFormat, options: &Options) -> Result<Search> {
+        command.stderr(Stdio::null());
+        let mut process = command.stdout(Stdio::piped()).spawn()?;
+        let Some(stdout) = process.stdout.take() else {
+            return Err(Error::other("No stdout"));
+        };
+        // Only rg's JSON output carries match offsets, the text formats are highlighted on our
+        // side.
+        let highlighter = match format {
+            OutputFormat::Vimgrep | OutputFormat::Grep => RegexBuilder::new(&options.prompt)
+                .case_insensitive(options.case.is_insensitive(&options.prompt))
+                .build()
+                .ok(),
+            _ => None,
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let search = Search::new(rx, Box::new(process));
+        let summary = search.summary_slot();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+
+            loop {
+                let mut line: Vec<u8> = Vec::new();
+                let num_bytes = reader.read_until(b'\n', &mut line).unwrap_or(0);
+                if num_bytes == 0 {
+                    break;
+                }
+                let result = match format.parse(&line, highlighter.as_ref()) {
+                    Some(Message::Result(result)) => result,
+                    Some(Message::Summary(s)) => {
+                        let _ = summary.set(s);
+                        continue;
+                    }
+                    None => continue,
+                };
+                if tx.send(result).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(search)
+    }
+
+    /// Runs `f` on every result in a background thread, keeping the ones it returns.
+    pub fn filter_map<F>(self, mut f: F) -> Search
+    where
+        F: FnMut(SearchResult) -> Option<SearchResult> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let source = self.rx;
+        thread::spawn(move || {
+            for result in source {
+                let Some(result) = f(result) else {
+                    continue;
+                };
+                if tx.send(result).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Search {
+            rx,
+            canceller: self.canceller,
+            summary: self.summary,
+            error: self.error,
+        }
+    }
+
+    pub fn try_recv(&self) -> std::result::Result<SearchResult, mpsc::TryRecvError> {
+        self.rx.try_recv()
+    }
+
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<SearchResult, mpsc::RecvTimeoutError> {
+        self.rx.recv_timeout(timeout)
+    }
+
+    pub fn cancel(&mut self) -> Result<()> {
+        self.canceller.cancel()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// ripgrep's `--json` messages
+    Json,
+    /// `path:line:column:text`
+    Vimgrep,
+    /// `path:line:text`
+    Grep,
+    /// One file path per line
+    #[value(skip)]
+    Paths,
+}
+
+enum Message {
+    Result(SearchResult),
+    Summary(Summary),
+}
+
+impl OutputFormat {
+    fn parse(&self, line: &[u8], highlighter: Option<&Regex>) -> Option<Message> {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches(['\n', '\r']);
+        let result = match self {
+            OutputFormat::Json => return Self::parse_json(line),
+            OutputFormat::Vimgrep => Self::parse_text(line, true, highlighter),
+            OutputFormat::Grep => Self::parse_text(line, false, highlighter),
+            OutputFormat::Paths => Some(SearchResult::file(line.to_string())),
+        };
+        result.map(Message::Result)
+    }
+
+    fn parse_json(line: &str) -> Option<Message> {
+        let m = match serde_json::from_str(line).ok()? {
+            RgMessage::Match(m) => m,
+            RgMessage::Summary(summary) => return Some(Message::Summary(summary.stats)),
+            RgMessage::Other => return None,
+        };
+        let text = m.lines.into_string();
+        let text = text.trim_end_matches(['\n', '\r']).to_string();
+        let matches: Vec<Range<usize>> = m
+            .submatches
+            .iter()
+            .map(|s| s.start.min(text.len())..s.end.min(text.len()))
+            .collect();
+        Some(Message::Result(SearchResult {
+            path: m.path.into_string(),
+            line_number: m.line_number? as i32,
+            column: matches.first().map_or(1, |r| r.start + 1),
+            text,
+            matches,
+            score: 0,
+            hit_lines: Vec::new(),
+        }))
+    }
+
+    fn parse_text(
+        line: &str,
+        has_column: bool,
+        highlighter: Option<&Regex>,
+    ) -> Option<SearchResult> {
+        let mut parts = line.splitn(if has_column { 4 } else { 3 }, ':');
+        let path = parts.next()?.to_string();
+        let line_number = parts.next()?.parse().ok()?;
+        let column = if has_column {
+            Some(parts.next()?.parse().ok()?)
+        } else {
+            None
+        };
+        let text = parts.next()?.to_string();
+
+        let matches: Vec<Range<usize>> = match highlighter {
+            Some(regex) => regex.find_iter(&text).map(|m| m.range()).collect(),
+            None => Vec::new(),
+        };
+
+        Some(SearchResult {
+            path,
+            line_number,
+            column: column.unwrap_or_else(|| matches.first().map_or(1, |r| r.start + 1)),
+            text,
+            matches,
+            score: 0,
+            hit_lines: Vec::new(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "lowercase")]
+enum RgMessage {
+    Match(RgMatch),
+    Summary(RgSummary),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct RgMatch {
+    path: RgData,
+    lines: RgData,
+    line_number: Option<u64>,
+    submatches: Vec<RgSubmatch>,
+}
+
+#[derive(Deserialize)]
+struct RgSummary {
+    stats: Summary,
+}
+
+#[derive(Deserialize)]
+struct RgSubmatch {
+    start: usize,
+    end: usize,
+}
+
+/// rg reports text as UTF-8 when it can and falls back to base64 otherwise.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RgData {
+    Text {
+        text: String,
+    },
+    Bytes {
+        #[allow(dead_code)]
+        bytes: String,
+    },
+}
+
+impl RgData {
+    fn into_string(self) -> String {
+        match self {
+            RgData::Text { text } => text,
+            RgData::Bytes { .. } => String::from("<non-UTF-8 data>"),
+        }
+    }
+}
+
+/// Runs an arbitrary shell command with the prompt and the roots appended as its arguments.
+///
+/// Useful for tools lazyrip doesn't know about, e.g. `ugrep -rnk --color=never` with the
+/// `vimgrep` format.
+pub struct CommandBackend {
+    command: String,
+    format: OutputFormat,
+}
+
+impl CommandBackend {
+    pub fn new(command: String, format: OutputFormat) -> CommandBackend {
+        CommandBackend { command, format }
+    }
+}
+
+impl SearchBackend for CommandBackend {
+    fn search(&self, options: &Options) -> Result<Search> {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(format!("{} \"$@\"", self.command))
+            .arg("lazyrip")
+            .arg(&options.prompt)
+            .args(&options.roots);
+        Search::spawn(command, self.format, options)
+    }
+}
+
+/// Returns whether `program` can be found in `$PATH`.
+pub fn is_installed(program: &str) -> bool {
+    let Some(path) = std::env::var_os("PATH") else {
+        return false;
+    };
+    std::env::split_paths(&path).any(|dir| dir.join(program).is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rg_matches_and_summary() {
+        let line = br#"{"type":"match","data":{"path":{"text":"src/a.rs"},"lines":{"text":"fn a() {}\n"},"line_number":3,"absolute_offset":0,"submatches":[{"match":{"text":"a"},"start":3,"end":4}]}}"#;
+        let Some(Message::Result(result)) = OutputFormat::Json.parse(line, None) else {
+            panic!("not a result");
+        };
+        assert_eq!(
+            (&result.path[..], result.line_number, result.column),
+            ("src/a.rs", 3, 4)
+        );
+        assert_eq!(result.matches, vec![3..4]);
+
+        let line = br#"{"type":"summary","data":{"elapsed_total":{"secs":0,"nanos":1,"human":"0s"},"stats":{"elapsed":{"secs":0,"nanos":1,"human":"0s"},"searches":12,"searches_with_match":0,"bytes_searched":4096,"bytes_printed":0,"matched_lines":0,"matches":0}}}"#;
+        let Some(Message::Summary(summary)) = OutputFormat::Json.parse(line, None) else {
+            panic!("not a summary");
+        };
+        assert_eq!((summary.files_searched, summary.bytes_searched), (12, 4096));
+    }
+}
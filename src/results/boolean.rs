@@ -0,0 +1,195 @@
+use super::backend::{Cancel, Search, SearchBackend, SearchResult};
+use super::ripgrep::Options;
+use std::collections::BTreeMap;
+use std::io::{Error, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+/// Finds the files matching every pattern of the query and none of its excluded ones.
+///
+/// Works like intersecting `rg --files-with-matches` for each pattern and removing
+/// `rg --files-with-matches` for each excluded one: every pattern is searched once over the roots
+/// and the files are narrowed down in memory. Each file is reported once, with its per-pattern
+/// hit counts and all hit lines.
+pub fn search(backend: Arc<dyn SearchBackend>, options: &Options) -> Search {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    let search = Search::new(rx, Box::new(Canceller(cancelled.clone())));
+    let error = search.error_slot();
+    let options = options.clone();
+
+    thread::spawn(move || {
+        let files = match run_chain(backend.as_ref(), &options, &cancelled) {
+            Ok(Some(files)) => files,
+            Ok(None) => return,
+            Err(e) => {
+                let _ = error.set(e.to_string());
+                return;
+            }
+        };
+        let patterns: Vec<&String> = std::iter::once(&options.prompt)
+            .chain(&options.required_patterns)
+            .collect();
+        for (path, hits) in files {
+            if tx.send(build_result(path, &patterns, hits)).is_err() {
+                break;
+            }
+        }
+    });
+
+    search
+}
+
+/// Maps each file left after all steps to its hit lines, per pattern. Returns `None` once
+/// cancelled.
+fn run_chain(
+    backend: &dyn SearchBackend,
+    options: &Options,
+    cancelled: &AtomicBool,
+) -> Result<Option<BTreeMap<String, Vec<Vec<i32>>>>> {
+    let step = |prompt: &String| {
+        let options = Options {
+            prompt: prompt.clone(),
+            ..options.clone()
+        };
+        collect(backend, &options, cancelled)
+    };
+
+    let Some(hits) = step(&options.prompt)? else {
+        return Ok(None);
+    };
+    let mut files: BTreeMap<String, Vec<Vec<i32>>> = hits
+        .into_iter()
+        .map(|(path, lines)| (path, vec![lines]))
+        .collect();
+
+    for pattern in &options.required_patterns {
+        if files.is_empty() {
+            return Ok(Some(files));
+        }
+        let Some(mut hits) = step(pattern)? else {
+            return Ok(None);
+        };
+        files.retain(|path, _| hits.contains_key(path));
+        for (path, file_hits) in files.iter_mut() {
+            file_hits.push(hits.remove(path).unwrap_or_default());
+        }
+    }
+
+    for pattern in &options.excluded_patterns {
+        if files.is_empty() {
+            return Ok(Some(files));
+        }
+        let Some(hits) = step(pattern)? else {
+            return Ok(None);
+        };
+        files.retain(|path, _| !hits.contains_key(path));
+    }
+    Ok(Some(files))
+}
+
+/// Waits for a whole search, grouping the hit lines by file. Returns `None` once cancelled.
+fn collect(
+    backend: &dyn SearchBackend,
+    options: &Options,
+    cancelled: &AtomicBool,
+) -> Result<Option<BTreeMap<String, Vec<i32>>>> {
+    let mut search = backend.search(options)?;
+    let mut hits: BTreeMap<String, Vec<i32>> = BTreeMap::new();
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            let _ = search.cancel();
+            return Ok(None);
+        }
+        match search.recv_timeout(Duration::from_millis(50)) {
+            Ok(result) => hits
+                .entry(result.path)
+                .or_default()
+                .push(result.line_number),
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    if let Some(error) = search.error() {
+        return Err(Error::other(error.to_string()));
+    }
+    let _ = search.cancel();
+    Ok(Some(hits))
+}
+
+/// A file result reading `path  foo: 3  bar: 1`, with the counts highlighted.
+fn build_result(path: String, patterns: &[&String], hits: Vec<Vec<i32>>) -> SearchResult {
+    let mut result = SearchResult::file(path);
+    for (pattern, lines) in patterns.iter().zip(&hits) {
+        result.text.push_str(&format!("  {}: ", pattern));
+        let count = lines.len().to_string();
+        result
+            .matches
+            .push(result.text.len()..result.text.len() + count.len());
+        result.text.push_str(&count);
+    }
+
+    let mut hit_lines: Vec<i32> = hits.into_iter().flatten().collect();
+    hit_lines.sort_unstable();
+    hit_lines.dedup();
+    result.hit_lines = hit_lines;
+    result
+}
+
+struct Canceller(Arc<AtomicBool>);
+
+impl Cancel for Canceller {
+    fn cancel(&mut self) -> Result<()> {
+        self.0.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::in_process::InProcess;
+    use super::super::query::Query;
+    use super::*;
+    use std::fs;
+    use std::time::Instant;
+
+    #[test]
+    fn keeps_files_with_all_patterns_and_none_excluded() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            ("both.rs", "unsafe {\n    transmute(x)\n}\nunsafe {}\n"),
+            (
+                "commented.rs",
+                "// SAFETY: checked\nunsafe { transmute(x) }\n",
+            ),
+            ("only_unsafe.rs", "unsafe {}\n"),
+        ];
+        for (path, content) in files {
+            fs::write(dir.path().join(path), content).unwrap();
+        }
+
+        let mut options = Options {
+            roots: vec![dir.path().to_string_lossy().into_owned()],
+            ..Default::default()
+        };
+        Query::parse("unsafe transmute -SAFETY:").apply(&mut options, Default::default());
+        let search = search(Arc::new(InProcess::with_index(None)), &options);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut results = Vec::new();
+        while Instant::now() < deadline {
+            match search.recv_timeout(Duration::from_millis(50)) {
+                Ok(result) => results.push(result),
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("both.rs"));
+        assert!(results[0].text.ends_with("  unsafe: 2  transmute: 1"));
+        assert_eq!(results[0].hit_lines, vec![1, 2, 4]);
+    }
+}
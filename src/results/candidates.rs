@@ -0,0 +1,307 @@
+//! Narrows a search down to the files modified lately, changed since a git ref or within a size
+//! range. The files passing these filters are listed first, then given to the backend as the
+//! paths to search.
+
+use super::backend::{Cancel, Search, SearchBackend, Summary};
+use super::in_process::{display_path, InProcess};
+use super::query::PathFilter;
+use super::ripgrep::Options;
+use std::collections::HashSet;
+use std::io::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How long the paths given to a single search can be, in bytes, to stay well within the
+/// command line limits, the lowest being Windows' 32K characters.
+const MAX_ROOTS_LEN: usize = 24 * 1024;
+
+/// Runs `start` on the files passing the file filters of `options`, a chunk of them at a time.
+/// Listing the files walks the whole tree, so it's done in a background thread.
+pub fn search(
+    backend: Arc<dyn SearchBackend>,
+    options: &Options,
+    start: fn(&Arc<dyn SearchBackend>, &Options) -> Result<Search>,
+) -> Search {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    let search = Search::new(rx, Box::new(Canceller(cancelled.clone())));
+    let summary = search.summary_slot();
+    let error = search.error_slot();
+    let options = options.clone();
+
+    thread::spawn(move || {
+        let files = match list(&options, &cancelled) {
+            Ok(files) => files,
+            Err(e) => {
+                let _ = error.set(e.to_string());
+                return;
+            }
+        };
+        // The listed files are the ones to search, the filters on paths are already applied.
+        let options = Options {
+            modified_within: None,
+            changed_since: None,
+            size: None,
+            path_globs: Vec::new(),
+            ..options
+        };
+        let mut total = Summary::default();
+        for roots in chunks(files) {
+            if cancelled.load(Ordering::Relaxed) {
+                return;
+            }
+            let options = Options {
+                roots,
+                ..options.clone()
+            };
+            let mut search = match start(&backend, &options) {
+                Ok(search) => search,
+                Err(e) => {
+                    let _ = error.set(e.to_string());
+                    return;
+                }
+            };
+            loop {
+                match search.recv_timeout(Duration::from_millis(50)) {
+                    Ok(result) => {
+                        if tx.send(result).is_err() {
+                            let _ = search.cancel();
+                            return;
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => (),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+                if cancelled.load(Ordering::Relaxed) {
+                    let _ = search.cancel();
+                    return;
+                }
+            }
+            if let Some(e) = search.error() {
+                let _ = error.set(e.to_string());
+                return;
+            }
+            if let Some(s) = search.summary() {
+                total.files_searched += s.files_searched;
+                total.bytes_searched += s.bytes_searched;
+            }
+        }
+        let _ = summary.set(total);
+    });
+
+    search
+}
+
+/// Splits `files` into lists of paths short enough for a command line.
+fn chunks(files: Vec<String>) -> Vec<Vec<String>> {
+    let mut chunks: Vec<Vec<String>> = Vec::new();
+    let mut len = 0;
+    for file in files {
+        if chunks.is_empty() || len + file.len() + 1 > MAX_ROOTS_LEN {
+            chunks.push(Vec::new());
+            len = 0;
+        }
+        len += file.len() + 1;
+        chunks.last_mut().unwrap().push(file);
+    }
+    chunks
+}
+
+/// The files the search would look into that pass its file filters, without a leading `./` so
+/// that the backends print them the same whatever the roots.
+fn list(options: &Options, cancelled: &AtomicBool) -> Result<Vec<String>> {
+    let path_filter = PathFilter::new(options);
+    let changed = match &options.changed_since {
+        Some(reference) => Some(changed_since(reference)?),
+        None => None,
+    };
+    let oldest = match options.modified_within {
+        Some(age) => Some(
+            SystemTime::now()
+                .checked_sub(age)
+                .ok_or_else(|| Error::other("modified: the age is too large"))?,
+        ),
+        None => None,
+    };
+
+    let mut files = Vec::new();
+    for entry in InProcess::build_walker(options)?.build() {
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        let Ok(entry) = entry else {
+            continue;
+        };
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        if let Some(changed) = &changed {
+            let Ok(path) = entry.path().canonicalize() else {
+                continue;
+            };
+            if !changed.contains(&path) {
+                continue;
+            }
+        }
+        if oldest.is_some() || options.size.is_some() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let modified = metadata.modified().ok();
+            if oldest.is_some_and(|oldest| modified.is_none_or(|m| m < oldest)) {
+                continue;
+            }
+            if options
+                .size
+                .is_some_and(|size| !size.contains(metadata.len()))
+            {
+                continue;
+            }
+        }
+        let path = display_path(entry.path());
+        if path_filter
+            .as_ref()
+            .is_some_and(|filter| !filter.matches(&path))
+        {
+            continue;
+        }
+        files.push(path);
+    }
+    Ok(files)
+}
+
+/// The files changed since `reference`, committed or not, and the untracked ones, canonicalized
+/// to compare them with the searched files whatever the roots look like.
+fn changed_since(reference: &str) -> Result<HashSet<PathBuf>> {
+    let current_dir = std::env::current_dir()?;
+    let diff = git(&["diff", "-z", "--name-only", "--relative", reference, "--"])?;
+    let untracked = git(&["ls-files", "-z", "--others", "--exclude-standard"])?;
+    Ok(diff
+        .into_iter()
+        .chain(untracked)
+        // Deleted files can't be searched anyway.
+        .filter_map(|path| current_dir.join(path).canonicalize().ok())
+        .collect())
+}
+
+/// The NUL separated paths printed by git, not quoted thanks to `-z`.
+fn git(args: &[&str]) -> Result<Vec<PathBuf>> {
+    let output = Command::new("git").args(args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = stderr.lines().next().unwrap_or("git failed").trim();
+        return Err(Error::other(format!("since: {}", message)));
+    }
+    Ok(output
+        .stdout
+        .split(|&b| b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| Path::new(&*String::from_utf8_lossy(path)).to_path_buf())
+        .collect())
+}
+
+struct Canceller(Arc<AtomicBool>);
+
+impl Cancel for Canceller {
+    fn cancel(&mut self) -> Result<()> {
+        self.0.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::ripgrep::{Mode, SizeRange};
+    use std::fs;
+
+    #[test]
+    fn lists_the_files_within_the_size_and_age() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("small.rs"), "fn a() {}\n").unwrap();
+        fs::write(dir.path().join("big.rs"), "x".repeat(2048)).unwrap();
+        let old = dir.path().join("old.rs");
+        fs::write(&old, "fn b() {}\n").unwrap();
+        let week_ago = SystemTime::now() - Duration::from_secs(7 * 24 * 3600);
+        fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(week_ago)
+            .unwrap();
+
+        let root = dir.path().to_string_lossy().into_owned();
+        let name = |file: &str| display_path(&dir.path().join(file));
+        let list = |options: Options| {
+            let mut files: Vec<String> = list(&options, &AtomicBool::new(false))
+                .unwrap()
+                .into_iter()
+                .collect();
+            files.sort();
+            files
+        };
+
+        let options = Options {
+            roots: vec![root.clone()],
+            size: Some(SizeRange { min: 0, max: 1023 }),
+            ..Options::default()
+        };
+        assert_eq!(list(options), [name("old.rs"), name("small.rs")]);
+
+        let options = Options {
+            roots: vec![root],
+            modified_within: Some(Duration::from_secs(3600)),
+            ..Options::default()
+        };
+        assert_eq!(list(options), [name("big.rs"), name("small.rs")]);
+    }
+
+    #[test]
+    fn searches_the_listed_files_under_the_current_directory() {
+        let options = Options {
+            mode: Mode::Content,
+            prompt: "^name = \"lazyrip\"".to_string(),
+            glob: "/Cargo.toml".to_string(),
+            roots: vec![".".to_string()],
+            size: Some(SizeRange {
+                min: 1,
+                max: u64::MAX,
+            }),
+            ..Options::default()
+        };
+        assert_eq!(
+            list(&options, &AtomicBool::new(false)).unwrap(),
+            ["Cargo.toml"]
+        );
+
+        let backend: Arc<dyn SearchBackend> = Arc::new(InProcess::with_index(None));
+        let search = search(backend, &options, |backend, options| {
+            backend.search(options)
+        });
+        let mut paths = Vec::new();
+        loop {
+            match search.recv_timeout(Duration::from_secs(10)) {
+                Ok(result) => paths.push(result.path),
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                Err(mpsc::RecvTimeoutError::Timeout) => panic!("the search didn't finish"),
+            }
+        }
+        assert_eq!(paths, ["Cargo.toml"]);
+        assert!(search.error().is_none());
+    }
+
+    #[test]
+    fn chunks_the_paths_within_the_limit() {
+        let files: Vec<String> = (0..10_000).map(|i| format!("src/{:05}.rs", i)).collect();
+        let chunks = chunks(files.clone());
+        assert!(chunks.len() > 1);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.iter().map(|f| f.len() + 1).sum::<usize>() <= MAX_ROOTS_LEN));
+        assert_eq!(chunks.concat(), files);
+    }
+}
@@ -0,0 +1,61 @@
+use super::backend::SearchResult;
+use super::ripgrep::{Mode, Options};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use regex::Regex;
+use std::ops::Range;
+
+/// Matches the prompt against file paths in the file name modes.
+pub enum FileMatcher {
+    Fuzzy(Box<SkimMatcherV2>, String),
+    Regex(Regex),
+}
+
+impl FileMatcher {
+    /// Returns `None` when the prompt is not a valid regex.
+    pub fn new(options: &Options) -> Option<FileMatcher> {
+        match options.mode {
+            Mode::RegexFiles => Regex::new(&options.prompt).ok().map(FileMatcher::Regex),
+            _ => Some(FileMatcher::Fuzzy(
+                Box::new(SkimMatcherV2::default().smart_case()),
+                options.prompt.clone(),
+            )),
+        }
+    }
+
+    /// Keeps `result` if its path matches, with the matched parts highlighted.
+    pub fn matches(&self, mut result: SearchResult) -> Option<SearchResult> {
+        match self {
+            FileMatcher::Fuzzy(matcher, pattern) => {
+                let (score, indices) = matcher.fuzzy_indices(&result.text, pattern)?;
+                result.matches = char_ranges(&result.text, &indices);
+                result.score = score;
+            }
+            FileMatcher::Regex(regex) => {
+                result.matches = regex.find_iter(&result.text).map(|m| m.range()).collect();
+                if result.matches.is_empty() {
+                    return None;
+                }
+            }
+        }
+        Some(result)
+    }
+}
+
+/// Converts the char indices reported by the fuzzy matcher to merged byte ranges.
+pub fn char_ranges(text: &str, indices: &[usize]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let mut indices = indices.iter().peekable();
+    for (i, (start, c)) in text.char_indices().enumerate() {
+        if indices.peek() != Some(&&i) {
+            continue;
+        }
+        indices.next();
+        let end = start + c.len_utf8();
+        match ranges.last_mut() {
+            Some(last) if last.end == start => last.end = end,
+            _ => ranges.push(start..end),
+        }
+    }
+    ranges
+}
@@ -0,0 +1,57 @@
+use super::backend::SearchResult;
+use super::files::char_ranges;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use std::ops::Range;
+
+/// Fuzzy matches a pattern against the path and text of results that were already found.
+pub struct ResultFilter {
+    matcher: SkimMatcherV2,
+    pattern: String,
+}
+
+pub struct FilterMatch {
+    pub score: i64,
+    /// Byte ranges of the matched characters in the path.
+    pub path: Vec<Range<usize>>,
+    /// Byte ranges of the matched characters in the text.
+    pub text: Vec<Range<usize>>,
+}
+
+impl ResultFilter {
+    pub fn new(pattern: String) -> ResultFilter {
+        ResultFilter {
+            matcher: SkimMatcherV2::default().smart_case(),
+            pattern,
+        }
+    }
+
+    pub fn matches(&self, result: &SearchResult) -> Option<FilterMatch> {
+        // File name results carry their path as text, there is nothing else to match.
+        if result.line_number == 0 {
+            let (score, indices) = self.matcher.fuzzy_indices(&result.path, &self.pattern)?;
+            return Some(FilterMatch {
+                score,
+                path: Vec::new(),
+                text: char_ranges(&result.text, &indices),
+            });
+        }
+
+        let choice = format!("{} {}", result.path, result.text);
+        let (score, indices) = self.matcher.fuzzy_indices(&choice, &self.pattern)?;
+        let path_len = result.path.chars().count();
+        let (path_indices, text_indices): (Vec<usize>, Vec<usize>) =
+            indices.into_iter().partition(|&i| i < path_len);
+        let text_indices: Vec<usize> = text_indices
+            .into_iter()
+            .filter(|&i| i > path_len)
+            .map(|i| i - path_len - 1)
+            .collect();
+
+        Some(FilterMatch {
+            score,
+            path: char_ranges(&result.path, &path_indices),
+            text: char_ranges(&result.text, &text_indices),
+        })
+    }
+}
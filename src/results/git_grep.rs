@@ -0,0 +1,103 @@
+use super::backend::{OutputFormat, Search, SearchBackend};
+use super::ripgrep::Options;
+use ignore::types::{Types, TypesBuilder};
+use std::io::Result;
+use std::process::Command;
+
+/// Searches the current git repository with `git grep`, for hosts without ripgrep.
+///
+/// Untracked files are searched as well, `.gitignore` is respected and globs are passed as
+/// pathspecs. git has no notion of hidden files or multiline matches, so `show_hidden` and
+/// `multiline` are ignored. git ORs pathspecs together, so file types are matched with rg's
+/// definitions on the paths git reports instead.
+pub struct GitGrep;
+
+impl SearchBackend for GitGrep {
+    fn search(&self, options: &Options) -> Result<Search> {
+        let mut command = Command::new("git");
+        command
+            .arg("grep")
+            .arg("--line-number")
+            .arg("--column")
+            .arg("--no-color")
+            .arg("--untracked")
+            .arg("-I")
+            .arg("--extended-regexp");
+        if options.case.is_insensitive(&options.prompt) {
+            command.arg("--ignore-case");
+        }
+        command
+            .arg("-e")
+            .arg(&options.prompt)
+            .arg("--")
+            .args(Self::pathspecs(options));
+        let search = Search::spawn(command, OutputFormat::Vimgrep, options)?;
+        Ok(Self::filter_types(search, options))
+    }
+
+    fn files(&self, options: &Options) -> Result<Search> {
+        let mut command = Command::new("git");
+        command
+            .arg("ls-files")
+            .arg("--cached")
+            .arg("--others")
+            .arg("--exclude-standard")
+            .arg("--")
+            .args(Self::pathspecs(options));
+        let search = Search::spawn(command, OutputFormat::Paths, options)?;
+        Ok(Self::filter_types(search, options))
+    }
+}
+
+impl GitGrep {
+    fn pathspecs(options: &Options) -> Vec<String> {
+        let mut pathspecs = options.roots.clone();
+        for glob in options.globs() {
+            let (magic, glob) = match glob.strip_prefix('!') {
+                Some(glob) => ("exclude,glob", glob),
+                None => ("glob", glob),
+            };
+            // Like rg, a glob without a slash matches at any depth, and one with a slash, leading
+            // or not, from the current directory, where pathspecs start.
+            let anchored = glob.contains('/');
+            let glob = glob.strip_prefix('/').unwrap_or(glob);
+            if anchored {
+                pathspecs.push(format!(":({}){}", magic, glob));
+            } else {
+                pathspecs.push(format!(":({})**/{}", magic, glob));
+            }
+        }
+        pathspecs
+    }
+
+    /// Drops the results outside of `options.types`, an unknown type matches nothing like in rg.
+    fn filter_types(search: Search, options: &Options) -> Search {
+        if options.types.is_empty() && options.types_not.is_empty() {
+            return search;
+        }
+        match Self::build_types(options) {
+            Some(types) => search.filter_map(move |result| {
+                match types.matched(&result.path, false).is_ignore() {
+                    true => None,
+                    false => Some(result),
+                }
+            }),
+            None => search.filter_map(|_| None),
+        }
+    }
+
+    fn build_types(options: &Options) -> Option<Types> {
+        let mut types = TypesBuilder::new();
+        types.add_defaults();
+        for definition in &options.type_definitions {
+            types.add_def(definition).ok()?;
+        }
+        for file_type in &options.types {
+            types.select(file_type);
+        }
+        for file_type in &options.types_not {
+            types.negate(file_type);
+        }
+        types.build().ok()
+    }
+}
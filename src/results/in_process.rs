@@ -0,0 +1,414 @@
+use super::backend::{Cancel, Search, SearchBackend, SearchResult, Summary};
+use super::index::Index;
+use super::ripgrep::{Case, Options};
+use grep_matcher::Matcher;
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use grep_searcher::{BinaryDetection, Searcher, SearcherBuilder, Sink, SinkMatch};
+use ignore::{overrides::OverrideBuilder, types::TypesBuilder, WalkBuilder, WalkState};
+use std::io::{Error, Result};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+/// Searches inside lazyrip with the crates ripgrep is built on, no `rg` binary needed.
+///
+/// Follows rg's defaults: `.gitignore`/`.ignore` files are respected, binary files are skipped
+/// and globs behave like `--glob`. When `lazyrip index` was run in the current directory, the
+/// trigram index is used to skip files that can't match.
+pub struct InProcess {
+    index: Option<Arc<Index>>,
+}
+
+impl SearchBackend for InProcess {
+    fn search(&self, options: &Options) -> Result<Search> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        let search = Search::new(rx, Box::new(Canceller(cancelled.clone())));
+        let summary = search.summary_slot();
+
+        // Like rg, an invalid pattern simply finds nothing.
+        let matcher = RegexMatcherBuilder::new()
+            .line_terminator((!options.multiline).then_some(b'\n'))
+            .multi_line(options.multiline)
+            .dot_matches_new_line(options.multiline)
+            .case_insensitive(options.case == Case::Insensitive)
+            .case_smart(options.case == Case::Smart)
+            .build(&options.prompt);
+        if let (Ok(matcher), Ok(walker)) = (matcher, Self::build_walker(options)) {
+            let cancelled = cancelled.clone();
+            let index = self.index.clone();
+            let prompt = options.prompt.clone();
+            let case_insensitive = options.case.is_insensitive(&options.prompt);
+            let multiline = options.multiline;
+            thread::spawn(move || {
+                let candidates = index
+                    .as_ref()
+                    .and_then(|index| Some((index, index.candidates(&prompt, case_insensitive)?)));
+                let files_searched = AtomicU64::new(0);
+                let bytes_searched = AtomicU64::new(0);
+                walker.build_parallel().run(|| {
+                    let tx = tx.clone();
+                    let matcher = matcher.clone();
+                    let cancelled = cancelled.clone();
+                    let (files_searched, bytes_searched) = (&files_searched, &bytes_searched);
+                    let candidates = candidates.as_ref();
+                    let mut searcher = SearcherBuilder::new()
+                        .line_number(true)
+                        .multi_line(multiline)
+                        .binary_detection(BinaryDetection::quit(b'\x00'))
+                        .build();
+
+                    Box::new(move |entry| {
+                        if cancelled.load(Ordering::Relaxed) {
+                            return WalkState::Quit;
+                        }
+                        let Ok(entry) = entry else {
+                            return WalkState::Continue;
+                        };
+                        if !entry.file_type().is_some_and(|t| t.is_file()) {
+                            return WalkState::Continue;
+                        }
+                        let path = display_path(entry.path());
+                        if let Some((index, candidates)) = candidates {
+                            let Ok(metadata) = entry.metadata() else {
+                                return WalkState::Continue;
+                            };
+                            if !index.should_search(candidates, Path::new(&path), &metadata) {
+                                return WalkState::Continue;
+                            }
+                        }
+
+                        let mut sink = ResultSink {
+                            path,
+                            matcher: &matcher,
+                            tx: &tx,
+                            cancelled: &cancelled,
+                        };
+                        let result = searcher.search_path(&matcher, entry.path(), &mut sink);
+                        files_searched.fetch_add(1, Ordering::Relaxed);
+                        let size = entry.metadata().map_or(0, |m| m.len());
+                        bytes_searched.fetch_add(size, Ordering::Relaxed);
+                        match result {
+                            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => WalkState::Quit,
+                            _ => WalkState::Continue,
+                        }
+                    })
+                });
+                if !cancelled.load(Ordering::Relaxed) {
+                    let _ = summary.set(Summary {
+                        files_searched: files_searched.into_inner(),
+                        bytes_searched: bytes_searched.into_inner(),
+                    });
+                }
+            });
+        }
+
+        Ok(search)
+    }
+
+    fn files(&self, options: &Options) -> Result<Search> {
+        list_files(options)
+    }
+}
+
+/// Walks the files a search with `options` would look into.
+pub fn list_files(options: &Options) -> Result<Search> {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    let Ok(walker) = InProcess::build_walker(options) else {
+        return Ok(Search::new(rx, Box::new(Canceller(cancelled))));
+    };
+
+    let walker_cancelled = cancelled.clone();
+    thread::spawn(move || {
+        walker.build_parallel().run(|| {
+            let tx = tx.clone();
+            let cancelled = walker_cancelled.clone();
+            Box::new(move |entry| {
+                if cancelled.load(Ordering::Relaxed) {
+                    return WalkState::Quit;
+                }
+                let Ok(entry) = entry else {
+                    return WalkState::Continue;
+                };
+                if !entry.file_type().is_some_and(|t| t.is_file()) {
+                    return WalkState::Continue;
+                }
+                match tx.send(SearchResult::file(display_path(entry.path()))) {
+                    Ok(_) => WalkState::Continue,
+                    Err(_) => WalkState::Quit,
+                }
+            })
+        });
+    });
+
+    Ok(Search::new(rx, Box::new(Canceller(cancelled))))
+}
+
+impl InProcess {
+    /// Uses the index of the current directory if there is one.
+    pub fn new() -> InProcess {
+        let index = Index::default_cache()
+            .ok()
+            .zip(std::env::current_dir().ok())
+            .and_then(|(cache, dir)| Index::load(&cache, &dir).ok().flatten());
+        Self::with_index(index)
+    }
+
+    pub fn with_index(index: Option<Index>) -> InProcess {
+        InProcess {
+            index: index.map(Arc::new),
+        }
+    }
+
+    /// Fails on invalid globs and unknown file types, which like rg find nothing.
+    pub fn build_walker(options: &Options) -> Result<WalkBuilder> {
+        let mut roots = options.roots.iter().map(String::as_str);
+        let mut walker = WalkBuilder::new(roots.next().unwrap_or("./"));
+        for root in roots {
+            walker.add(root);
+        }
+        walker.hidden(!options.show_hidden);
+
+        let mut overrides = OverrideBuilder::new(std::env::current_dir()?);
+        for glob in options.globs() {
+            overrides.add(glob).map_err(Error::other)?;
+        }
+        walker.overrides(overrides.build().map_err(Error::other)?);
+
+        let mut types = TypesBuilder::new();
+        types.add_defaults();
+        for definition in &options.type_definitions {
+            types.add_def(definition).map_err(Error::other)?;
+        }
+        for file_type in &options.types {
+            types.select(file_type);
+        }
+        for file_type in &options.types_not {
+            types.negate(file_type);
+        }
+        walker.types(types.build().map_err(Error::other)?);
+        Ok(walker)
+    }
+}
+
+/// rg prints paths relative to the current directory without a leading `./`.
+pub fn display_path(path: &Path) -> String {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    path.to_string_lossy().into_owned()
+}
+
+struct Canceller(Arc<AtomicBool>);
+
+impl Cancel for Canceller {
+    fn cancel(&mut self) -> Result<()> {
+        self.0.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+struct ResultSink<'s> {
+    path: String,
+    matcher: &'s RegexMatcher,
+    tx: &'s mpsc::Sender<SearchResult>,
+    cancelled: &'s AtomicBool,
+}
+
+impl Sink for ResultSink<'_> {
+    type Error = Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool> {
+        let bytes = mat.bytes();
+        let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+        let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+
+        let mut matches = Vec::new();
+        self.matcher
+            .find_iter(bytes, |m| {
+                matches.push(m.start()..m.end());
+                true
+            })
+            .map_err(Error::other)?;
+
+        let result = SearchResult {
+            path: self.path.clone(),
+            line_number: mat.line_number().unwrap_or(0) as i32,
+            column: matches.first().map_or(1, |m| m.start + 1),
+            text: String::from_utf8_lossy(bytes).into_owned(),
+            matches,
+            score: 0,
+            hit_lines: Vec::new(),
+        };
+        if self.tx.send(result).is_err() {
+            return Err(Error::from(std::io::ErrorKind::BrokenPipe));
+        }
+        Ok(!self.cancelled.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ripgrep::Ripgrep;
+    use super::*;
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    /// A small tree with ignored, hidden and binary files.
+    fn fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let files: &[(&str, &[u8])] = &[
+            (
+                "src/main.rs",
+                b"fn main() {\n    // TODO: parse args\n    run();\n}\n",
+            ),
+            (
+                "src/auth/login.rs",
+                b"// TODO: rate limit\nfn login() {}\nfn logout() {}\n",
+            ),
+            ("README.md", b"# fixture\nTODO: write docs\n"),
+            (".config/settings.toml", b"todo = \"TODO: hidden\"\n"),
+            ("target/debug/out.rs", b"// TODO: ignored\n"),
+            ("data.bin", b"TODO\x00\x01\x02"),
+            (".gitignore", b"target/\n"),
+        ];
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        // .gitignore is only honoured inside a git repository.
+        fs::create_dir(root.join(".git")).unwrap();
+        dir
+    }
+
+    fn options(dir: &tempfile::TempDir, prompt: &str, glob: &str, show_hidden: bool) -> Options {
+        Options {
+            show_hidden,
+            prompt: prompt.to_string(),
+            glob: glob.to_string(),
+            roots: vec![dir.path().to_string_lossy().into_owned()],
+            ..Default::default()
+        }
+    }
+
+    /// The results of `search` until it's done, failing if it takes too long.
+    fn wait(search: &Search) -> Vec<SearchResult> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut results = Vec::new();
+        loop {
+            match search.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(r) => results.push(r),
+                Err(mpsc::RecvTimeoutError::Disconnected) => return results,
+                Err(mpsc::RecvTimeoutError::Timeout) => panic!("the search didn't finish"),
+            }
+        }
+    }
+
+    fn collect(backend: &dyn SearchBackend, options: &Options) -> Vec<(String, i32, String)> {
+        let search = backend.search(options).unwrap();
+        let mut results: Vec<_> = wait(&search)
+            .into_iter()
+            .map(|r| (r.path, r.line_number, r.text))
+            .collect();
+        results.sort();
+        results
+    }
+
+    fn relative(dir: &tempfile::TempDir, results: Vec<(String, i32, String)>) -> Vec<String> {
+        let prefix = format!("{}/", dir.path().to_string_lossy());
+        results
+            .into_iter()
+            .map(|(path, line, _)| format!("{}:{}", path.strip_prefix(&prefix).unwrap(), line))
+            .collect()
+    }
+
+    #[test]
+    fn reports_exact_submatches() {
+        let dir = fixture();
+        let search = InProcess::with_index(None)
+            .search(&options(&dir, "log(in|out)", "*.rs", false))
+            .unwrap();
+        let mut results = wait(&search);
+        results.sort_by_key(|r| r.line_number);
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].text, "fn login() {}");
+        assert_eq!(results[0].matches, vec![3..8]);
+        assert_eq!(results[0].column, 4);
+        assert_eq!(results[1].matches, vec![3..9]);
+    }
+
+    #[test]
+    fn honours_ignore_hidden_and_globs() {
+        let dir = fixture();
+
+        let found = relative(
+            &dir,
+            collect(
+                &InProcess::with_index(None),
+                &options(&dir, "TODO", "", false),
+            ),
+        );
+        assert_eq!(
+            found,
+            ["README.md:2", "src/auth/login.rs:1", "src/main.rs:2"]
+        );
+
+        let found = relative(
+            &dir,
+            collect(
+                &InProcess::with_index(None),
+                &options(&dir, "TODO", "", true),
+            ),
+        );
+        assert!(found.contains(&String::from(".config/settings.toml:1")));
+        assert!(!found
+            .iter()
+            .any(|f| f.starts_with("target/") || f.starts_with(".git/")));
+
+        let found = relative(
+            &dir,
+            collect(
+                &InProcess::with_index(None),
+                &options(&dir, "TODO", "*.rs;!**/auth/**", false),
+            ),
+        );
+        assert_eq!(found, ["src/main.rs:2"]);
+    }
+
+    #[test]
+    fn cancel_stops_the_search() {
+        let dir = fixture();
+        let mut search = InProcess::with_index(None)
+            .search(&options(&dir, "TODO", "", false))
+            .unwrap();
+        search.cancel().unwrap();
+        // Fails if the search kept running after being cancelled.
+        wait(&search);
+    }
+
+    #[test]
+    #[ignore = "needs rg installed, run with --ignored"]
+    fn matches_rg() {
+        let dir = fixture();
+        for (prompt, glob, show_hidden) in [
+            ("TODO", "", false),
+            ("TODO", "", true),
+            ("fn \\w+", "*.rs", false),
+            ("TODO", "!src/**", true),
+            ("(", "", false),
+        ] {
+            let options = options(&dir, prompt, glob, show_hidden);
+            assert_eq!(
+                collect(&InProcess::with_index(None), &options),
+                collect(&Ripgrep, &options),
+                "prompt {:?}, glob {:?}, hidden {}",
+                prompt,
+                glob,
+                show_hidden
+            );
+        }
+    }
+}
@@ -0,0 +1,543 @@
+//! An on-disk trigram index, in the spirit of codesearch and zoekt.
+//!
+//! The index maps every (ASCII lowercased) trigram to the files containing it. A regex is
+//! turned into a trigram query that every matching file must satisfy, so only the candidate
+//! files have to be read and verified. Files that changed or appeared since the index was
+//! built are always searched, which keeps a stale index correct, just slower.
+
+use ignore::WalkBuilder;
+use regex_syntax::hir::{Class, Hir, HirKind};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, Metadata};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 8] = b"LZRIDX1\n";
+/// Larger files are left out of the index and always searched.
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+struct FileEntry {
+    path: PathBuf,
+    mtime: (u64, u32),
+    size: u64,
+}
+
+impl FileEntry {
+    fn is_fresh(&self, metadata: &Metadata) -> bool {
+        self.size == metadata.len() && Some(self.mtime) == mtime(metadata)
+    }
+}
+
+pub struct Index {
+    files: Vec<FileEntry>,
+    ids: HashMap<PathBuf, u32>,
+    postings: HashMap<u32, Vec<u32>>,
+}
+
+pub struct UpdateStats {
+    pub files: usize,
+    pub reindexed: usize,
+    pub path: PathBuf,
+}
+
+impl Index {
+    /// Where the indexes are stored, under the user's cache directory.
+    pub fn default_cache() -> Result<PathBuf> {
+        match dirs::cache_dir() {
+            Some(cache) => Ok(cache.join("lazyrip").join("index")),
+            None => Err(Error::new(ErrorKind::NotFound, "no cache directory")),
+        }
+    }
+
+    /// Where the index of `root` is stored in the `cache` directory.
+    pub fn path(cache: &Path, root: &Path) -> Result<PathBuf> {
+        let root = fs::canonicalize(root)?;
+        let name = root.to_string_lossy().replace(['/', '\\'], "%");
+        Ok(cache.join(name))
+    }
+
+    pub fn exists(cache: &Path, root: &Path) -> bool {
+        Self::path(cache, root).is_ok_and(|p| p.is_file())
+    }
+
+    /// Loads the index of `root` from `cache`, if one was built.
+    pub fn load(cache: &Path, root: &Path) -> Result<Option<Index>> {
+        let file = match File::open(Self::path(cache, root)?) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Self::read(&mut BufReader::new(file)).map(Some)
+    }
+
+    /// Builds the index of `root` into `cache`, re-reading only the files whose mtime or size
+    /// changed.
+    pub fn update(cache: &Path, root: &Path) -> Result<UpdateStats> {
+        // A corrupt or outdated index is simply rebuilt from scratch.
+        let old = Self::load(cache, root).unwrap_or(None);
+        let mut index = Index {
+            files: Vec::new(),
+            ids: HashMap::new(),
+            postings: HashMap::new(),
+        };
+        let mut reused: HashMap<u32, u32> = HashMap::new();
+        let mut reindexed = 0;
+
+        for entry in WalkBuilder::new(root).build() {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.len() > MAX_FILE_SIZE {
+                continue;
+            }
+            let Some(mtime) = mtime(&metadata) else {
+                continue;
+            };
+
+            let path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            let id = index.files.len() as u32;
+            let old_id = old.as_ref().and_then(|old| {
+                let old_id = *old.ids.get(path)?;
+                old.files[old_id as usize]
+                    .is_fresh(&metadata)
+                    .then_some(old_id)
+            });
+
+            match old_id {
+                Some(old_id) => {
+                    reused.insert(old_id, id);
+                }
+                None => {
+                    let Ok(content) = fs::read(entry.path()) else {
+                        continue;
+                    };
+                    reindexed += 1;
+                    // Binary files are recorded without trigrams, the searcher skips them anyway.
+                    if !content.contains(&0) {
+                        for trigram in trigrams(&content) {
+                            index.postings.entry(trigram).or_default().push(id);
+                        }
+                    }
+                }
+            }
+
+            index.ids.insert(path.to_path_buf(), id);
+            index.files.push(FileEntry {
+                path: path.to_path_buf(),
+                mtime,
+                size: metadata.len(),
+            });
+        }
+
+        if let Some(old) = old {
+            for (trigram, ids) in old.postings {
+                let ids = ids.iter().filter_map(|id| reused.get(id).copied());
+                index.postings.entry(trigram).or_default().extend(ids);
+            }
+        }
+        for ids in index.postings.values_mut() {
+            ids.sort_unstable();
+        }
+        index.postings.retain(|_, ids| !ids.is_empty());
+
+        let path = Self::path(cache, root)?;
+        fs::create_dir_all(path.parent().unwrap_or(&path))?;
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        index.write(&mut writer)?;
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&tmp_path, &path)?;
+
+        Ok(UpdateStats {
+            files: index.files.len(),
+            reindexed,
+            path,
+        })
+    }
+
+    /// The indexed files that may match `pattern`, or `None` if the index can't narrow it down.
+    ///
+    /// The trigrams are only lowercased for ASCII, so a `case_insensitive` search parses its
+    /// non-ASCII letters as classes, which don't narrow anything down.
+    pub fn candidates(&self, pattern: &str, case_insensitive: bool) -> Option<HashSet<u32>> {
+        let hir = regex_syntax::ParserBuilder::new()
+            .case_insensitive(case_insensitive)
+            .build()
+            .parse(pattern)
+            .ok()?;
+        self.eval(&Query::from_hir(&hir).query)
+    }
+
+    /// Whether a file has to be searched, given the result of [`Index::candidates`].
+    ///
+    /// Files missing from the index or modified since it was built are always searched.
+    pub fn should_search(
+        &self,
+        candidates: &HashSet<u32>,
+        path: &Path,
+        metadata: &Metadata,
+    ) -> bool {
+        match self.ids.get(path) {
+            Some(id) => !self.files[*id as usize].is_fresh(metadata) || candidates.contains(id),
+            None => true,
+        }
+    }
+
+    fn eval(&self, query: &Query) -> Option<HashSet<u32>> {
+        match query {
+            Query::All => None,
+            Query::Trigrams(trigrams) => {
+                let mut lists: Vec<&[u32]> = trigrams
+                    .iter()
+                    .map(|t| self.postings.get(t).map_or(&[][..], |ids| &ids[..]))
+                    .collect();
+                lists.sort_by_key(|ids| ids.len());
+                let mut ids: HashSet<u32> = lists[0].iter().copied().collect();
+                for other in &lists[1..] {
+                    ids.retain(|id| other.binary_search(id).is_ok());
+                }
+                Some(ids)
+            }
+            Query::And(queries) => queries
+                .iter()
+                .fold(None, |acc, q| match (acc, self.eval(q)) {
+                    (None, ids) | (ids, None) => ids,
+                    (Some(a), Some(b)) => Some(a.intersection(&b).copied().collect()),
+                }),
+            Query::Or(queries) => {
+                let mut ids = HashSet::new();
+                for q in queries {
+                    ids.extend(self.eval(q)?);
+                }
+                Some(ids)
+            }
+        }
+    }
+
+    fn write(&self, w: &mut impl Write) -> Result<()> {
+        w.write_all(MAGIC)?;
+        write_u64(w, self.files.len() as u64)?;
+        for file in &self.files {
+            let path = file.path.to_string_lossy();
+            write_u64(w, path.len() as u64)?;
+            w.write_all(path.as_bytes())?;
+            write_u64(w, file.mtime.0)?;
+            write_u64(w, file.mtime.1.into())?;
+            write_u64(w, file.size)?;
+        }
+        write_u64(w, self.postings.len() as u64)?;
+        for (trigram, ids) in &self.postings {
+            write_u64(w, (*trigram).into())?;
+            write_u64(w, ids.len() as u64)?;
+            for id in ids {
+                w.write_all(&id.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read(r: &mut impl Read) -> Result<Index> {
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a lazyrip index"));
+        }
+
+        let num_files = read_u64(r)? as usize;
+        let mut files = Vec::with_capacity(num_files);
+        let mut ids = HashMap::with_capacity(num_files);
+        for id in 0..num_files {
+            let mut path = vec![0; read_u64(r)? as usize];
+            r.read_exact(&mut path)?;
+            let path = PathBuf::from(String::from_utf8_lossy(&path).into_owned());
+            let mtime = (read_u64(r)?, read_u64(r)? as u32);
+            let size = read_u64(r)?;
+            ids.insert(path.clone(), id as u32);
+            files.push(FileEntry { path, mtime, size });
+        }
+
+        let num_trigrams = read_u64(r)? as usize;
+        let mut postings = HashMap::with_capacity(num_trigrams);
+        for _ in 0..num_trigrams {
+            let trigram = read_u64(r)? as u32;
+            let len = read_u64(r)? as usize;
+            let mut ids = Vec::with_capacity(len);
+            let mut buf = [0; 4];
+            for _ in 0..len {
+                r.read_exact(&mut buf)?;
+                ids.push(u32::from_le_bytes(buf));
+            }
+            postings.insert(trigram, ids);
+        }
+
+        Ok(Index {
+            files,
+            ids,
+            postings,
+        })
+    }
+}
+
+fn mtime(metadata: &Metadata) -> Option<(u64, u32)> {
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((mtime.as_secs(), mtime.subsec_nanos()))
+}
+
+fn write_u64(w: &mut impl Write, n: u64) -> Result<()> {
+    w.write_all(&n.to_le_bytes())
+}
+
+fn read_u64(r: &mut impl Read) -> Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn trigrams(content: &[u8]) -> HashSet<u32> {
+    content
+        .windows(3)
+        .map(|w| {
+            let [a, b, c] = [w[0], w[1], w[2]].map(|b| b.to_ascii_lowercase());
+            u32::from_be_bytes([0, a, b, c])
+        })
+        .collect()
+}
+
+/// The trigrams a file must contain to possibly match a regex.
+enum Query {
+    All,
+    Trigrams(Vec<u32>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+/// What is known about a sub-expression while building its [`Query`].
+struct Info {
+    /// The single (lowercased) string the expression matches, if there is one.
+    exact: Option<Vec<u8>>,
+    query: Query,
+}
+
+impl Query {
+    fn literal(bytes: &[u8]) -> Query {
+        if bytes.len() < 3 {
+            Query::All
+        } else {
+            Query::Trigrams(trigrams(bytes).into_iter().collect())
+        }
+    }
+
+    fn and(queries: Vec<Query>) -> Query {
+        let mut queries: Vec<Query> = queries
+            .into_iter()
+            .filter(|q| !matches!(q, Query::All))
+            .collect();
+        match queries.len() {
+            0 => Query::All,
+            1 => queries.remove(0),
+            _ => Query::And(queries),
+        }
+    }
+
+    fn or(queries: Vec<Query>) -> Query {
+        if queries.iter().any(|q| matches!(q, Query::All)) {
+            Query::All
+        } else {
+            Query::Or(queries)
+        }
+    }
+
+    fn from_hir(hir: &Hir) -> Info {
+        let exact = |bytes: Vec<u8>| Info {
+            query: Query::literal(&bytes),
+            exact: Some(bytes),
+        };
+        let inexact = |query: Query| Info { exact: None, query };
+
+        match hir.kind() {
+            HirKind::Empty | HirKind::Look(_) => exact(Vec::new()),
+            HirKind::Literal(lit) => exact(lit.0.to_ascii_lowercase()),
+            HirKind::Class(class) => match Self::class_byte(class) {
+                Some(b) => exact(vec![b]),
+                None => inexact(Query::All),
+            },
+            HirKind::Capture(capture) => Self::from_hir(&capture.sub),
+            HirKind::Repetition(rep) => {
+                let sub = Self::from_hir(&rep.sub);
+                match (rep.min, rep.max) {
+                    (1, Some(1)) => sub,
+                    (0, _) => inexact(Query::All),
+                    _ => inexact(sub.query),
+                }
+            }
+            HirKind::Concat(subs) => {
+                let mut queries = Vec::new();
+                let mut run: Vec<u8> = Vec::new();
+                let mut all_exact = true;
+                for sub in subs {
+                    let info = Self::from_hir(sub);
+                    match info.exact {
+                        Some(bytes) => run.extend(bytes),
+                        None => {
+                            all_exact = false;
+                            queries.push(Query::literal(&run));
+                            run.clear();
+                            queries.push(info.query);
+                        }
+                    }
+                }
+                if all_exact {
+                    exact(run)
+                } else {
+                    queries.push(Query::literal(&run));
+                    inexact(Query::and(queries))
+                }
+            }
+            HirKind::Alternation(subs) => inexact(Query::or(
+                subs.iter().map(|s| Self::from_hir(s).query).collect(),
+            )),
+        }
+    }
+
+    /// A class that only matches one ASCII letter in either case, like `(?i)a`.
+    fn class_byte(class: &Class) -> Option<u8> {
+        let mut bytes: Vec<u8> = match class {
+            Class::Unicode(class) => {
+                let mut bytes = Vec::new();
+                for range in class.ranges() {
+                    for c in range.start()..=range.end() {
+                        bytes.push(u8::try_from(c).ok().filter(u8::is_ascii)?);
+                        if bytes.len() > 2 {
+                            return None;
+                        }
+                    }
+                }
+                bytes
+            }
+            Class::Bytes(class) => {
+                let mut bytes = Vec::new();
+                for range in class.ranges() {
+                    for b in range.start()..=range.end() {
+                        bytes.push(b);
+                        if bytes.len() > 2 {
+                            return None;
+                        }
+                    }
+                }
+                bytes
+            }
+        };
+        bytes.iter_mut().for_each(|b| *b = b.to_ascii_lowercase());
+        bytes.dedup();
+        match bytes[..] {
+            [b] => Some(b),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(files: &[(&str, &str)]) -> Index {
+        let mut index = Index {
+            files: Vec::new(),
+            ids: HashMap::new(),
+            postings: HashMap::new(),
+        };
+        for (id, (path, content)) in files.iter().enumerate() {
+            for trigram in trigrams(content.as_bytes()) {
+                index.postings.entry(trigram).or_default().push(id as u32);
+            }
+            index.ids.insert(PathBuf::from(path), id as u32);
+            index.files.push(FileEntry {
+                path: PathBuf::from(path),
+                mtime: (0, 0),
+                size: 0,
+            });
+        }
+        index
+    }
+
+    fn candidates(index: &Index, pattern: &str) -> Option<Vec<u32>> {
+        let mut ids: Vec<u32> = index.candidates(pattern, false)?.into_iter().collect();
+        ids.sort();
+        Some(ids)
+    }
+
+    #[test]
+    fn narrows_down_candidates() {
+        let index = index(&[
+            ("a", "fn parse_args() {}"),
+            ("b", "fn parse() {}"),
+            ("c", "let args = Args::new();"),
+        ]);
+
+        assert_eq!(candidates(&index, "parse"), Some(vec![0, 1]));
+        assert_eq!(candidates(&index, "parse_args"), Some(vec![0]));
+        assert_eq!(candidates(&index, r"\bargs\b"), Some(vec![0, 2]));
+        assert_eq!(candidates(&index, "(?i)ARGS::new"), Some(vec![2]));
+        assert_eq!(candidates(&index, "parse.*args"), Some(vec![0]));
+        assert_eq!(candidates(&index, "parse|new"), Some(vec![0, 1, 2]));
+        assert_eq!(candidates(&index, "missing"), Some(vec![]));
+    }
+
+    #[test]
+    fn keeps_files_differing_in_non_ascii_case() {
+        let index = index(&[("a", "let äpfel = 1;"), ("b", "let Äpfel = 2;")]);
+        let ids = |pattern, case_insensitive| {
+            let mut ids: Vec<u32> = index
+                .candidates(pattern, case_insensitive)?
+                .into_iter()
+                .collect();
+            ids.sort();
+            Some(ids)
+        };
+
+        assert_eq!(ids("äpfel", false), Some(vec![0]));
+        assert_eq!(ids("äpfel", true), Some(vec![0, 1]));
+        assert_eq!(ids("(?i)äpfel", false), Some(vec![0, 1]));
+        assert_eq!(ids("PFEL", true), Some(vec![0, 1]));
+    }
+
+    #[test]
+    fn falls_back_when_the_regex_has_no_trigrams() {
+        let index = index(&[("a", "abc")]);
+
+        assert_eq!(candidates(&index, "ab"), None);
+        assert_eq!(candidates(&index, "a.c"), None);
+        assert_eq!(candidates(&index, "abc|x"), None);
+        assert_eq!(candidates(&index, "(abc)?"), None);
+        assert_eq!(candidates(&index, "("), None);
+    }
+
+    #[test]
+    fn round_trips_and_updates_incrementally() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.rs"), "fn alpha() {}").unwrap();
+        fs::write(dir.path().join("b.rs"), "fn beta() {}").unwrap();
+
+        let stats = Index::update(cache.path(), dir.path()).unwrap();
+        assert_eq!((stats.files, stats.reindexed), (2, 2));
+
+        fs::write(dir.path().join("b.rs"), "fn gamma() {}").unwrap();
+        let stats = Index::update(cache.path(), dir.path()).unwrap();
+        assert_eq!((stats.files, stats.reindexed), (2, 1));
+
+        assert!(stats.path.starts_with(cache.path()));
+        let index = Index::load(cache.path(), dir.path()).unwrap().unwrap();
+        let id = |path: &str| index.ids[Path::new(path)];
+        assert_eq!(candidates(&index, "alpha"), Some(vec![id("a.rs")]));
+        assert_eq!(candidates(&index, "gamma"), Some(vec![id("b.rs")]));
+        assert_eq!(candidates(&index, "beta"), Some(vec![]));
+    }
+}
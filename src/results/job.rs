@@ -0,0 +1,558 @@
+use super::backend::{Search, SearchBackend, SearchResult, Summary};
+use super::boolean;
+use super::candidates;
+use super::files::FileMatcher;
+use super::filter::{FilterMatch, ResultFilter};
+use super::query::{PathFilter, PostFilter};
+use super::ripgrep::{Mode, Options};
+use crate::theme::Theme;
+use ratatui::{prelude::*, widgets::*};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::io::Result;
+use std::ops::Range;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+pub struct Job<'a> {
+    search: Search,
+    finished: bool,
+    started: Instant,
+    /// How long the search took, once finished.
+    elapsed: Option<Duration>,
+    patch: Option<Patch>,
+    /// Keep the results sorted by score, for fuzzy matching.
+    ranked: bool,
+    theme: Theme,
+
+    results_items: Vec<ListItem<'a>>,
+    results: Vec<SearchResult>,
+    /// The paths having results.
+    files: HashSet<String>,
+    /// Where the results pushed since the last [`Job::take_inserted`] are shown.
+    inserted: Vec<usize>,
+    filter: Option<Filter<'a>>,
+}
+
+/// The results left after narrowing them down with a [`ResultFilter`], best match first.
+struct Filter<'a> {
+    filter: ResultFilter,
+    /// Indices into `Job::results`.
+    indices: Vec<usize>,
+    scores: Vec<i64>,
+    items: Vec<ListItem<'a>>,
+}
+
+impl<'a> Filter<'a> {
+    fn clear(&mut self) {
+        self.indices.clear();
+        self.scores.clear();
+        self.items.clear();
+    }
+
+    /// Adds the matching ones of the results stored at `indices`, after the shown results
+    /// matching as well. Returns where the shown results moved, and where the added ones are.
+    fn add_all(
+        &mut self,
+        indices: impl Iterator<Item = usize>,
+        results: &[SearchResult],
+        theme: &Theme,
+    ) -> (Vec<usize>, Vec<usize>) {
+        let mut added: Vec<(i64, usize, ListItem<'a>)> = indices
+            .filter_map(|index| {
+                let m = self.filter.matches(&results[index])?;
+                Some((
+                    m.score,
+                    index,
+                    Job::build_item(theme, &results[index], Some(&m)),
+                ))
+            })
+            .collect();
+        added.sort_by_key(|&(score, _, _)| Reverse(score));
+
+        let plan = merge_plan(
+            self.scores.iter().copied(),
+            added.iter().map(|&(score, _, _)| score),
+        );
+        let (scores, (indices, items)): (Vec<_>, (Vec<_>, Vec<_>)) = added
+            .into_iter()
+            .map(|(score, index, item)| (score, (index, item)))
+            .unzip();
+        merge(&mut self.scores, scores, &plan);
+        merge(&mut self.indices, indices, &plan);
+        merge(&mut self.items, items, &plan);
+        positions(&plan)
+    }
+}
+
+/// How to merge lists sorted by descending keys: whether each merged item comes from the new
+/// list, the old items going first among equal keys.
+fn merge_plan(old: impl Iterator<Item = i64>, new: impl Iterator<Item = i64>) -> Vec<bool> {
+    let (mut old, mut new) = (old.peekable(), new.peekable());
+    let mut plan = Vec::new();
+    loop {
+        let from_new = match (old.peek(), new.peek()) {
+            (Some(o), Some(n)) => n > o,
+            (Some(_), None) => false,
+            (None, Some(_)) => true,
+            (None, None) => return plan,
+        };
+        match from_new {
+            true => new.next(),
+            false => old.next(),
+        };
+        plan.push(from_new);
+    }
+}
+
+/// Merges `new` into `old` following `plan`, see [`merge_plan`].
+fn merge<T>(old: &mut Vec<T>, new: Vec<T>, plan: &[bool]) {
+    let (mut old_items, mut new_items) = (std::mem::take(old).into_iter(), new.into_iter());
+    old.extend(plan.iter().filter_map(|&from_new| match from_new {
+        true => new_items.next(),
+        false => old_items.next(),
+    }));
+}
+
+/// Where the old items and the new ones are once merged following `plan`.
+fn positions(plan: &[bool]) -> (Vec<usize>, Vec<usize>) {
+    let (mut old, mut new) = (Vec::new(), Vec::new());
+    for (position, &from_new) in plan.iter().enumerate() {
+        match from_new {
+            true => new.push(position),
+            false => old.push(position),
+        }
+    }
+    (old, new)
+}
+
+/// A re-search of some changed files, applied to the results once it completes.
+struct Patch {
+    search: Option<Search>,
+    paths: HashSet<String>,
+    results: Vec<SearchResult>,
+}
+
+impl<'a> Job<'a> {
+    pub fn new(backend: &Arc<dyn SearchBackend>, options: &Options, theme: &Theme) -> Result<Self> {
+        Ok(Job {
+            search: Self::start(backend, options)?,
+            finished: false,
+            started: Instant::now(),
+            elapsed: None,
+            patch: None,
+            ranked: options.mode == Mode::FuzzyFiles,
+            theme: theme.clone(),
+
+            results_items: Vec::new(),
+            results: Vec::new(),
+            files: HashSet::new(),
+            inserted: Vec::new(),
+            filter: None,
+        })
+    }
+
+    fn start(backend: &Arc<dyn SearchBackend>, options: &Options) -> Result<Search> {
+        if options.has_file_filters() {
+            return Ok(candidates::search(backend.clone(), options, Self::start));
+        }
+
+        let search = if options.mode == Mode::BooleanFiles {
+            boolean::search(backend.clone(), options)
+        } else if options.mode == Mode::Content {
+            let search = backend.search(options)?;
+            match PostFilter::new(options) {
+                Some(filter) => search.filter_map(move |result| filter.matches(result)),
+                None => search,
+            }
+        } else {
+            let files = backend.files(options)?;
+            match FileMatcher::new(options) {
+                Some(matcher) => files.filter_map(move |result| matcher.matches(result)),
+                None => files.filter_map(|_| None),
+            }
+        };
+        Ok(match PathFilter::new(options) {
+            Some(filter) => {
+                search.filter_map(move |result| filter.matches(&result.path).then_some(result))
+            }
+            None => search,
+        })
+    }
+
+    /// Narrows the results down to the ones fuzzy matching `pattern`, an empty pattern shows
+    /// all of them again in their original order.
+    pub fn set_filter(&mut self, pattern: &str) {
+        if pattern.is_empty() {
+            self.filter = None;
+            return;
+        }
+
+        let mut filter = Filter {
+            filter: ResultFilter::new(pattern.to_string()),
+            indices: Vec::new(),
+            scores: Vec::new(),
+            items: Vec::new(),
+        };
+        filter.add_all(0..self.results.len(), &self.results, &self.theme);
+        self.inserted.clear();
+        self.filter = Some(filter);
+    }
+
+    /// Maps the index of a shown result to its index in `results`.
+    fn result_index(&self, index: usize) -> usize {
+        match &self.filter {
+            Some(filter) => filter.indices[index],
+            None => index,
+        }
+    }
+
+    pub fn get_results_items(&self) -> &[ListItem<'a>] {
+        match &self.filter {
+            Some(filter) => &filter.items[..],
+            None => &self.results_items[..],
+        }
+    }
+
+    pub fn get_result(&self, index: usize) -> (&str, i32) {
+        let result = &self.results[self.result_index(index)];
+        (&result.path, result.line_number)
+    }
+
+    pub fn current_num_results(&self) -> usize {
+        self.get_results_items().len()
+    }
+
+    /// The index of the result in `path` closest to `line_number`, preferring lines that still
+    /// read `text` in case lines were added or removed above it.
+    pub fn find(&self, path: &str, line_number: i32, text: &str) -> Option<usize> {
+        (0..self.current_num_results())
+            .map(|index| (index, &self.results[self.result_index(index)]))
+            .filter(|(_, r)| r.path == path)
+            .min_by_key(|(_, r)| (r.text != text, (r.line_number - line_number).abs()))
+            .map(|(index, _)| index)
+    }
+
+    pub fn get_hit_lines(&self, index: usize) -> &[i32] {
+        &self.results[self.result_index(index)].hit_lines
+    }
+
+    /// Byte ranges of the matches inside the text of the result at `index`.
+    pub fn get_matches(&self, index: usize) -> &[Range<usize>] {
+        &self.results[self.result_index(index)].matches
+    }
+
+    pub fn get_text(&self, index: usize) -> &str {
+        &self.results[self.result_index(index)].text
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed.unwrap_or_else(|| self.started.elapsed())
+    }
+
+    pub fn summary(&self) -> Option<Summary> {
+        self.search.summary()
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.search.error()
+    }
+
+    /// The number of results before filtering.
+    pub fn num_results(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn num_files(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_patching(&self) -> bool {
+        self.patch.is_some()
+    }
+
+    /// Replaces the results of `paths` by searching again only the ones in `searched_paths`.
+    pub fn patch(
+        &mut self,
+        backend: &Arc<dyn SearchBackend>,
+        options: &Options,
+        paths: HashSet<String>,
+        searched_paths: Vec<String>,
+    ) -> Result<()> {
+        let search = if searched_paths.is_empty() {
+            None
+        } else {
+            let options = Options {
+                roots: searched_paths,
+                ..options.clone()
+            };
+            Some(Self::start(backend, &options)?)
+        };
+
+        self.patch = Some(Patch {
+            search,
+            paths,
+            results: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Reads up to `max` of the results found since the last call, showing them all at once.
+    /// Returns whether anything changed.
+    pub fn read_results(&mut self, max: usize) -> Result<bool> {
+        if self.patch.is_some() {
+            return self.read_patch(max);
+        }
+
+        let mut results = Vec::new();
+        let mut disconnected = false;
+        while results.len() < max {
+            match self.search.try_recv() {
+                Ok(result) => results.push(result),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    disconnected = true;
+                    break;
+                }
+            }
+        }
+        let changed = !results.is_empty();
+        self.push_all(results);
+
+        // Finishing changes the status line, even without results.
+        if disconnected && !self.finished {
+            self.finalize()?;
+            return Ok(true);
+        }
+        Ok(changed)
+    }
+
+    fn read_patch(&mut self, max: usize) -> Result<bool> {
+        let Some(patch) = self.patch.as_mut() else {
+            return Ok(false);
+        };
+        if let Some(search) = patch.search.as_mut() {
+            let mut read = 0;
+            while read < max {
+                match search.try_recv() {
+                    Ok(result) => {
+                        patch.results.push(result);
+                        read += 1;
+                    }
+                    Err(mpsc::TryRecvError::Empty) => return Ok(read > 0),
+                    Err(mpsc::TryRecvError::Disconnected) => break,
+                }
+            }
+            if read == max {
+                return Ok(true);
+            }
+            search.cancel()?;
+        }
+
+        let Some(patch) = self.patch.take() else {
+            return Ok(false);
+        };
+        self.apply_patch(patch);
+        Ok(true)
+    }
+
+    /// Swaps in the new results of each patched file where its old results were.
+    fn apply_patch(&mut self, patch: Patch) {
+        let mut new_results: HashMap<String, Vec<SearchResult>> = HashMap::new();
+        for result in patch.results {
+            new_results
+                .entry(result.path.clone())
+                .or_default()
+                .push(result);
+        }
+
+        let old_results = std::mem::take(&mut self.results);
+        self.results_items.clear();
+        self.files.clear();
+        if let Some(filter) = self.filter.as_mut() {
+            filter.clear();
+        }
+        let mut results = Vec::with_capacity(old_results.len());
+        for result in old_results {
+            if !patch.paths.contains(&result.path) {
+                results.push(result);
+                continue;
+            }
+            if let Some(mut file_results) = new_results.remove(&result.path) {
+                file_results.sort_by_key(|r| r.line_number);
+                results.extend(file_results);
+            }
+        }
+
+        let mut new_files: Vec<Vec<SearchResult>> = new_results.into_values().collect();
+        new_files.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+        for mut file_results in new_files {
+            file_results.sort_by_key(|r| r.line_number);
+            results.extend(file_results);
+        }
+        self.push_all(results);
+        // Every result moved, the selection is found again by its file and line instead.
+        self.inserted.clear();
+    }
+
+    /// Adds `results` after the ones already there, or where their score ranks them.
+    fn push_all(&mut self, mut results: Vec<SearchResult>) {
+        if results.is_empty() {
+            return;
+        }
+        for result in &results {
+            if !self.files.contains(&result.path) {
+                self.files.insert(result.path.clone());
+            }
+        }
+        let items: Vec<ListItem<'a>> = results
+            .iter()
+            .map(|result| Self::build_item(&self.theme, result, None))
+            .collect();
+
+        let (moved, added) = if self.ranked {
+            // A stable sort keeps the order they were found in among equal scores.
+            let mut ranked: Vec<(SearchResult, ListItem<'a>)> =
+                results.into_iter().zip(items).collect();
+            ranked.sort_by_key(|(result, _)| Reverse(result.score));
+            let (ranked_results, items): (Vec<_>, Vec<_>) = ranked.into_iter().unzip();
+            let plan = merge_plan(
+                self.results.iter().map(|r| r.score),
+                ranked_results.iter().map(|r| r.score),
+            );
+            merge(&mut self.results, ranked_results, &plan);
+            merge(&mut self.results_items, items, &plan);
+            let (moved, added) = positions(&plan);
+            (Some(moved), added)
+        } else {
+            let start = self.results.len();
+            self.results.append(&mut results);
+            self.results_items.extend(items);
+            (None, (start..self.results.len()).collect())
+        };
+
+        let (moved, added) = match self.filter.as_mut() {
+            Some(filter) => {
+                if let Some(moved) = &moved {
+                    for index in filter.indices.iter_mut() {
+                        *index = moved[*index];
+                    }
+                }
+                let (moved, added) = filter.add_all(added.into_iter(), &self.results, &self.theme);
+                (Some(moved), added)
+            }
+            None => (moved, added),
+        };
+        if let Some(moved) = moved {
+            for position in self.inserted.iter_mut() {
+                *position = moved[*position];
+            }
+        }
+        self.inserted.extend(added);
+    }
+
+    /// Where the results pushed since the last call are now shown, in order.
+    pub fn take_inserted(&mut self) -> Vec<usize> {
+        let mut inserted = std::mem::take(&mut self.inserted);
+        inserted.sort_unstable();
+        inserted
+    }
+
+    fn build_item(
+        theme: &Theme,
+        result: &SearchResult,
+        filter_match: Option<&FilterMatch>,
+    ) -> ListItem<'a> {
+        let path_style = theme.path;
+        let match_style = theme.matched;
+        let filter_style = theme.filter;
+        let (path_filter, text_filter) = match filter_match {
+            Some(m) => (&m.path[..], &m.text[..]),
+            None => (&[][..], &[][..]),
+        };
+
+        let text_layers = [
+            (&result.matches[..], match_style),
+            (text_filter, filter_style),
+        ];
+        if result.line_number == 0 {
+            return ListItem::new(Line::from(Self::highlight(
+                &result.text,
+                path_style,
+                &text_layers,
+            )));
+        }
+
+        let mut spans = Self::highlight(&result.path, path_style, &[(path_filter, filter_style)]);
+        spans.extend([
+            Span::raw(":"),
+            Span::styled(result.line_number.to_string(), theme.line_number),
+            Span::raw(":"),
+            Span::raw(result.column.to_string()),
+            Span::raw(":"),
+        ]);
+        spans.extend(Self::highlight(
+            &result.text,
+            Style::default(),
+            &text_layers,
+        ));
+        ListItem::new(Line::from(spans))
+    }
+
+    /// Splits `text` into spans, patching `style` with each layer's style where its ranges are.
+    fn highlight(text: &str, style: Style, layers: &[(&[Range<usize>], Style)]) -> Vec<Span<'a>> {
+        let mut bounds = vec![0, text.len()];
+        for (ranges, _) in layers {
+            for range in ranges.iter() {
+                bounds.extend([range.start, range.end]);
+            }
+        }
+        bounds.retain(|&b| b <= text.len() && text.is_char_boundary(b));
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        bounds
+            .windows(2)
+            .map(|w| {
+                let mut span_style = style;
+                for (ranges, layer_style) in layers {
+                    if ranges.iter().any(|r| r.start <= w[0] && w[1] <= r.end) {
+                        span_style = span_style.patch(*layer_style);
+                    }
+                }
+                // Multiline matches are shown on a single line.
+                Span::styled(text[w[0]..w[1]].replace('\n', "⏎"), span_style)
+            })
+            .collect()
+    }
+
+    pub fn finalize(&mut self) -> Result<()> {
+        if !self.finished {
+            self.finished = true;
+            self.elapsed = Some(self.started.elapsed());
+            self.search.cancel()?;
+        }
+        if let Some(search) = self.patch.as_mut().and_then(|p| p.search.as_mut()) {
+            search.cancel()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_batches_after_equal_scores() {
+        let plan = merge_plan([9, 5, 5, 1].into_iter(), [7, 5, 0].into_iter());
+        let mut merged = vec!["a9", "b5", "c5", "d1"];
+        merge(&mut merged, vec!["x7", "y5", "z0"], &plan);
+        assert_eq!(merged, ["a9", "x7", "b5", "c5", "y5", "d1", "z0"]);
+        assert_eq!(positions(&plan), (vec![0, 2, 3, 5], vec![1, 4, 6]));
+    }
+}
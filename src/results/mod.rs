@@ -1,55 +1,325 @@
+pub mod backend;
+mod boolean;
+mod candidates;
+mod files;
+mod filter;
+pub mod git_grep;
+pub mod in_process;
+pub mod index;
+mod job;
 mod preview;
-mod ripgrep;
+pub mod query;
+pub mod ripgrep;
+mod watch;
 
+use crate::theme::Theme;
 use ratatui::widgets::*;
-use std::io::Result;
+use std::collections::HashSet;
+use std::io::{Error, Result};
+use std::path::Path;
 use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How many results are read at most between two renders, each batch being added at once.
+const MAX_RESULTS_PER_TICK: usize = 1000;
+
+/// What the status line tells about the current search.
+pub struct Status {
+    pub running: bool,
+    pub elapsed: Duration,
+    /// The number of results, before filtering.
+    pub results: usize,
+    /// The number of results left by the filter.
+    pub shown: usize,
+    pub files: usize,
+    pub selection: Option<usize>,
+    pub summary: Option<backend::Summary>,
+    /// Why the search failed, it then found nothing or only part of the results.
+    pub error: Option<String>,
+}
 
 pub struct Manager<'a> {
     should_execute: bool,
     should_rerender: bool,
-    job: Option<ripgrep::Job<'a>>,
+    backend: Arc<dyn backend::SearchBackend>,
+    job: Option<job::Job<'a>>,
     preview_job: Option<preview::PreviewJob>,
+    watcher: Option<watch::Watcher>,
+    /// Why watching stopped, shown until watching again or the next search.
+    watch_error: Option<String>,
+    /// Files changed while the job was busy, re-searched once it's done.
+    changes: HashSet<String>,
     pub show_preview: bool,
 
+    /// The prompt as typed, `options` holds what was parsed from it.
+    prompt: String,
+    /// Used when the prompt has no `case:` qualifier.
+    default_case: ripgrep::Case,
     options: ripgrep::Options,
+    /// The scopes applied to `options`, the latest last.
+    scopes: Vec<Scope>,
+    /// The file types picked to search and to skip, added to the `lang:` ones of the prompt.
+    file_types: Vec<String>,
+    file_types_not: Vec<String>,
+    filter: String,
+
+    selection_index: Option<usize>,
+    selection_preview: Option<preview::Preview>,
+    /// The file, line and text to select again once the results were patched or searched again.
+    restored_selection: Option<(String, i32, String)>,
+    /// Selects the first result as soon as there is one.
+    auto_select: bool,
+    /// Whether the selection was made by `auto_select`, it then follows the top result.
+    auto_selected: bool,
+    theme: Theme,
+}
+
+/// What a scope does with the path of the result it was taken from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ScopeKind {
+    /// Searches only in the file.
+    File,
+    /// Searches only in the file's directory.
+    Directory,
+    ExcludeFile,
+    ExcludeDirectory,
+}
 
+/// A narrowing of the search around a result, popped to get the search it narrowed back.
+#[derive(Clone, Debug)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    pub path: String,
+    /// The roots before the scope replaced them.
+    previous_roots: Vec<String>,
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            ScopeKind::File => write!(f, "{}", self.path),
+            ScopeKind::Directory => write!(f, "{}/", self.path),
+            ScopeKind::ExcludeFile => write!(f, "not {}", self.path),
+            ScopeKind::ExcludeDirectory => write!(f, "not {}/", self.path),
+        }
+    }
+}
+
+/// A search put aside when drilling down, restored as it was, results and selection included.
+pub struct SavedSearch<'a> {
+    prompt: String,
+    default_case: ripgrep::Case,
+    options: ripgrep::Options,
+    scopes: Vec<Scope>,
+    file_types: Vec<String>,
+    file_types_not: Vec<String>,
+    filter: String,
+    job: Option<job::Job<'a>>,
     selection_index: Option<usize>,
     selection_preview: Option<preview::Preview>,
+    auto_selected: bool,
 }
 
 impl<'a> Manager<'a> {
-    pub fn new() -> Manager<'a> {
-        return Manager {
+    pub fn new(backend: Box<dyn backend::SearchBackend>, theme: Theme) -> Manager<'a> {
+        Manager {
             should_execute: false,
             should_rerender: true,
+            backend: Arc::from(backend),
             job: None,
             preview_job: None,
+            watcher: None,
+            watch_error: None,
+            changes: HashSet::new(),
             show_preview: true,
 
-            options: ripgrep::Options {
-                show_hidden: false,
-                prompt: String::new(),
-                glob: String::new(),
-            },
+            prompt: String::new(),
+            default_case: ripgrep::Case::default(),
+            options: ripgrep::Options::default(),
+            scopes: Vec::new(),
+            file_types: Vec::new(),
+            file_types_not: Vec::new(),
+            filter: String::new(),
 
             selection_index: None,
             selection_preview: None,
-        };
+            restored_selection: None,
+            auto_select: false,
+            auto_selected: false,
+            theme,
+        }
     }
 
+    /// Parses `prompt` as a [`query::Query`] in the content and boolean modes, the file name modes
+    /// match it as is.
     pub fn set_prompt(&mut self, prompt: String) {
-        self.options.prompt = prompt;
+        self.prompt = prompt;
+        self.apply_prompt();
+        self.should_execute = true;
+    }
+
+    fn apply_prompt(&mut self) {
+        match self.options.mode {
+            // A multiline prompt is a single regex, its newlines aren't term separators.
+            ripgrep::Mode::Content | ripgrep::Mode::BooleanFiles if self.options.multiline => {
+                query::Query::default().apply(&mut self.options, self.default_case);
+                self.options.prompt = self.prompt.clone();
+            }
+            ripgrep::Mode::Content | ripgrep::Mode::BooleanFiles => {
+                query::Query::parse(&self.prompt).apply(&mut self.options, self.default_case)
+            }
+            ripgrep::Mode::FuzzyFiles | ripgrep::Mode::RegexFiles => {
+                query::Query::default().apply(&mut self.options, self.default_case);
+                self.options.prompt = self.prompt.clone();
+            }
+        }
+        self.options.types.extend(self.file_types.iter().cloned());
+        self.options
+            .types_not
+            .extend(self.file_types_not.iter().cloned());
+    }
+
+    /// Sets the file types picked to search and to skip, as `-t` and `-T`.
+    pub fn set_file_types(&mut self, file_types: Vec<String>, file_types_not: Vec<String>) {
+        self.file_types = file_types;
+        self.file_types_not = file_types_not;
+        self.apply_prompt();
         self.should_execute = true;
     }
 
+    pub fn file_types(&self) -> (&[String], &[String]) {
+        (&self.file_types, &self.file_types_not)
+    }
+
+    /// Custom file types, as `name:glob`.
+    pub fn set_type_definitions(&mut self, definitions: Vec<String>) {
+        self.options.type_definitions = definitions;
+        self.should_execute = true;
+    }
+
+    pub fn type_definitions(&self) -> &[String] {
+        &self.options.type_definitions
+    }
+
+    pub fn set_default_case(&mut self, case: ripgrep::Case) {
+        self.default_case = case;
+        self.apply_prompt();
+        self.should_execute = true;
+    }
+
+    pub fn set_auto_select(&mut self, auto_select: bool) {
+        self.auto_select = auto_select;
+        self.should_rerender = true;
+    }
+
     pub fn set_glob(&mut self, glob: String) {
         self.options.glob = glob;
         self.should_execute = true;
     }
 
+    /// Replaces the roots, dropping the scopes.
+    pub fn set_roots(&mut self, roots: Vec<String>) {
+        self.options.roots = roots;
+        self.options.scope_globs.clear();
+        self.scopes.clear();
+        self.should_execute = true;
+    }
+
+    /// The roots as set, before any scope replaced them.
+    pub fn roots(&self) -> &[String] {
+        self.scopes
+            .iter()
+            .find(|scope| matches!(scope.kind, ScopeKind::File | ScopeKind::Directory))
+            .map_or(&self.options.roots, |scope| &scope.previous_roots)
+    }
+
+    /// Narrows the search around the selected result, returns false without a selection or when
+    /// its directory is the current one, which can't be excluded.
+    pub fn push_scope(&mut self, kind: ScopeKind) -> bool {
+        let Some((path, _, _)) = self.selection_anchor() else {
+            return false;
+        };
+        let path = match kind {
+            ScopeKind::File | ScopeKind::ExcludeFile => path,
+            ScopeKind::Directory | ScopeKind::ExcludeDirectory => match Path::new(&path).parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy().into_owned(),
+                _ if kind == ScopeKind::Directory => ".".to_string(),
+                _ => return false,
+            },
+        };
+
+        let previous_roots = self.options.roots.clone();
+        match kind {
+            ScopeKind::File | ScopeKind::Directory => self.options.roots = vec![path.clone()],
+            ScopeKind::ExcludeFile => self.options.scope_globs.push(exclude_glob(&path, false)),
+            ScopeKind::ExcludeDirectory => self.options.scope_globs.push(exclude_glob(&path, true)),
+        }
+        self.scopes.push(Scope {
+            kind,
+            path,
+            previous_roots,
+        });
+        self.should_execute = true;
+        true
+    }
+
+    /// Undoes the latest scope, returns false when there is none.
+    pub fn pop_scope(&mut self) -> bool {
+        let Some(scope) = self.scopes.pop() else {
+            return false;
+        };
+        match scope.kind {
+            ScopeKind::File | ScopeKind::Directory => self.options.roots = scope.previous_roots,
+            ScopeKind::ExcludeFile | ScopeKind::ExcludeDirectory => {
+                self.options.scope_globs.pop();
+            }
+        }
+        self.should_execute = true;
+        true
+    }
+
+    pub fn scopes(&self) -> &[Scope] {
+        &self.scopes
+    }
+
+    /// Fuzzy filters the current results, keeping the selected result selected if it's still
+    /// shown.
+    pub fn set_filter(&mut self, filter: String) -> Result<()> {
+        self.filter = filter;
+        let selected = self.kept_selection();
+        let Some(job) = self.job.as_mut() else {
+            return Ok(());
+        };
+
+        job.set_filter(&self.filter);
+        let selection = selected.and_then(|(path, line_number, text)| {
+            job.find(&path, line_number, &text)
+                .filter(|&index| job.get_result(index).1 == line_number)
+        });
+        self.select(selection)
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.set_mode(self.options.mode.next());
+    }
+
+    pub fn set_mode(&mut self, mode: ripgrep::Mode) {
+        self.options.mode = mode;
+        self.apply_prompt();
+        self.should_execute = true;
+    }
+
+    pub fn mode(&self) -> ripgrep::Mode {
+        self.options.mode
+    }
+
     pub fn toggle_hidden(&mut self) {
-        self.options.show_hidden = !self.options.show_hidden;
+        self.set_hidden(!self.options.show_hidden);
+    }
+
+    pub fn set_hidden(&mut self, show_hidden: bool) {
+        self.options.show_hidden = show_hidden;
         self.should_execute = true;
     }
 
@@ -57,6 +327,50 @@ impl<'a> Manager<'a> {
         self.options.show_hidden
     }
 
+    pub fn toggle_multiline(&mut self) {
+        self.set_multiline(!self.options.multiline);
+    }
+
+    pub fn set_multiline(&mut self, multiline: bool) {
+        self.options.multiline = multiline;
+        self.apply_prompt();
+        self.should_execute = true;
+    }
+
+    pub fn is_multiline(&self) -> bool {
+        self.options.multiline
+    }
+
+    pub fn toggle_watch(&mut self) {
+        self.watch_error = None;
+        match self.watcher {
+            Some(_) => self.watcher = None,
+            None => self.watch(),
+        }
+    }
+
+    /// Starts watching the roots of the search, or turns watching off if they can't be watched.
+    fn watch(&mut self) {
+        match watch::Watcher::new(&self.options) {
+            Ok(watcher) => self.watcher = Some(watcher),
+            Err(e) => self.stop_watching(e),
+        }
+    }
+
+    fn stop_watching(&mut self, error: Error) {
+        self.watcher = None;
+        self.changes.clear();
+        self.watch_error = Some(format!("watch: {}", error));
+    }
+
+    pub fn watch_error(&self) -> Option<&str> {
+        self.watch_error.as_deref()
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.watcher.is_some()
+    }
+
     pub fn next(&mut self) -> Result<()> {
         let Some(job) = self.job.as_ref() else {
             return Ok(());
@@ -90,8 +404,242 @@ impl<'a> Manager<'a> {
         Ok(())
     }
 
+    /// Moves the selection by `delta` results, stopping at the first and the last one.
+    pub fn move_selection(&mut self, delta: isize) -> Result<()> {
+        let num_results = self.num_results();
+        if num_results == 0 {
+            return Ok(());
+        }
+        let index = match self.selection_index {
+            Some(index) => index.saturating_add_signed(delta),
+            None if delta > 0 => delta as usize - 1,
+            None => return Ok(()),
+        };
+        self.select(Some(index.min(num_results - 1)))
+    }
+
+    pub fn select_last(&mut self) -> Result<()> {
+        match self.num_results() {
+            0 => Ok(()),
+            n => self.select(Some(n - 1)),
+        }
+    }
+
+    /// Selects the first result of the next file.
+    pub fn next_file(&mut self) -> Result<()> {
+        let Some(job) = self.job.as_ref() else {
+            return Ok(());
+        };
+        let Some(index) = self.selection_index else {
+            return self.next();
+        };
+        let path = job.get_result(index).0;
+        let next = (index + 1..job.current_num_results()).find(|&i| job.get_result(i).0 != path);
+        match next {
+            Some(next) => self.select(Some(next)),
+            None => Ok(()),
+        }
+    }
+
+    /// Selects the first result of the previous file.
+    pub fn prev_file(&mut self) -> Result<()> {
+        let Some(job) = self.job.as_ref() else {
+            return Ok(());
+        };
+        let Some(index) = self.selection_index else {
+            return Ok(());
+        };
+        let first_of_file = |end: usize| {
+            let path = job.get_result(end).0;
+            (0..end)
+                .rev()
+                .take_while(|&i| job.get_result(i).0 == path)
+                .last()
+                .unwrap_or(end)
+        };
+        let current = first_of_file(index);
+        match current.checked_sub(1) {
+            Some(last_of_prev) => self.select(Some(first_of_file(last_of_prev))),
+            None => Ok(()),
+        }
+    }
+
+    fn num_results(&self) -> usize {
+        self.job.as_ref().map_or(0, |job| job.current_num_results())
+    }
+
+    /// Selects the result at `index` if there's one, returning whether there was.
+    pub fn select_at(&mut self, index: usize) -> Result<bool> {
+        let Some(job) = self.job.as_ref() else {
+            return Ok(false);
+        };
+        if index >= job.current_num_results() {
+            return Ok(false);
+        }
+        self.select(Some(index))?;
+        Ok(true)
+    }
+
+    /// Whether the preview of the selected result is shown, for it to take the focus.
+    pub fn has_preview(&self) -> bool {
+        self.show_preview && self.selection_preview.is_some()
+    }
+
+    /// Moves the cursor of a preview `height` lines high, as in [`Manager::get_preview`].
+    pub fn move_preview_cursor(&mut self, lines: i32, columns: i32, height: i32) {
+        if let Some(preview) = self.selection_preview.as_mut() {
+            preview.move_cursor(lines, columns, height);
+        }
+    }
+
+    /// Puts the cursor of the preview on the character shown at `row` and `column`, from the top
+    /// left inside its borders.
+    pub fn click_preview(&mut self, row: u16, column: u16, height: i32) {
+        if let Some(preview) = self.selection_preview.as_mut() {
+            preview.click(row, column, height);
+        }
+    }
+
+    pub fn preview_cursor(&self, height: i32) -> Option<(u16, u16)> {
+        self.selection_preview.as_ref()?.cursor_position(height)
+    }
+
+    /// The identifier at the cursor of the preview.
+    pub fn preview_identifier(&self) -> Option<String> {
+        self.selection_preview.as_ref()?.identifier()
+    }
+
+    /// Scrolls a preview `height` lines high, as in [`Manager::get_preview`].
+    pub fn scroll_preview(&mut self, lines: i32, height: i32) {
+        if let Some(preview) = self.selection_preview.as_mut() {
+            preview.scroll(lines, height);
+        }
+    }
+
+    /// The progress of the current search, none before typing one.
+    pub fn status(&self) -> Option<Status> {
+        let job = self.job.as_ref()?;
+        Some(Status {
+            running: !job.is_finished() || job.is_patching(),
+            elapsed: job.elapsed(),
+            results: job.num_results(),
+            shown: job.current_num_results(),
+            files: job.num_files(),
+            selection: self.selection_index,
+            summary: job.summary(),
+            error: job.error().or(self.watch_error()).map(str::to_string),
+        })
+    }
+
+    /// The identifier at the first match of the selected result, `None` for files.
+    pub fn selected_identifier(&self) -> Option<String> {
+        if self.options.mode != ripgrep::Mode::Content {
+            return None;
+        }
+        let index = self.selection_index?;
+        let job = self.job.as_ref()?;
+        let offset = job.get_matches(index).first()?.start;
+        identifier_at(job.get_text(index), offset).map(str::to_string)
+    }
+
+    /// The character the first match of the selected result starts at, in its line.
+    fn selected_match_column(&self) -> Option<usize> {
+        if self.options.mode != ripgrep::Mode::Content {
+            return None;
+        }
+        let index = self.selection_index?;
+        let job = self.job.as_ref()?;
+        let start = job.get_matches(index).first()?.start;
+        let text = job.get_text(index);
+        // The offsets are on the raw bytes, they may not fall between chars of the lossy text.
+        Some(text.get(..start)?.chars().count())
+    }
+
+    /// Takes the current search out, its job keeps its results until it's restored.
+    pub fn save_search(&mut self) -> SavedSearch<'a> {
+        self.preview_job = None;
+        self.restored_selection = None;
+        SavedSearch {
+            prompt: self.prompt.clone(),
+            default_case: self.default_case,
+            options: self.options.clone(),
+            scopes: self.scopes.clone(),
+            file_types: self.file_types.clone(),
+            file_types_not: self.file_types_not.clone(),
+            filter: self.filter.clone(),
+            job: self.job.take(),
+            selection_index: self.selection_index.take(),
+            selection_preview: self.selection_preview.take(),
+            auto_selected: std::mem::take(&mut self.auto_selected),
+        }
+    }
+
+    /// Stops a search taken out by [`Manager::save_search`] that won't be restored.
+    pub fn discard_search(saved: SavedSearch<'a>) -> Result<()> {
+        match saved.job {
+            Some(mut job) => job.finalize(),
+            None => Ok(()),
+        }
+    }
+
+    /// Brings back a search taken out by [`Manager::save_search`], without searching again.
+    pub fn restore_search(&mut self, saved: SavedSearch<'a>) -> Result<()> {
+        if let Some(mut job) = self.job.take() {
+            job.finalize()?;
+        }
+        self.prompt = saved.prompt;
+        self.default_case = saved.default_case;
+        self.options = saved.options;
+        self.scopes = saved.scopes;
+        self.file_types = saved.file_types;
+        self.file_types_not = saved.file_types_not;
+        self.filter = saved.filter;
+        self.job = saved.job;
+        self.selection_index = saved.selection_index;
+        self.auto_selected = saved.auto_selected;
+        self.restored_selection = None;
+        self.preview_job = None;
+        self.should_execute = false;
+        self.should_rerender = true;
+        match saved.selection_preview {
+            Some(preview) if self.show_preview => {
+                self.selection_preview = Some(preview);
+                Ok(())
+            }
+            _ => self.update_preview(),
+        }
+    }
+
+    /// Whether a result above the selection can be selected, the selection having been moved
+    /// there rather than made by `auto_select`.
+    pub fn can_select_prev(&self) -> bool {
+        !self.auto_selected && self.selection_index.is_some_and(|index| index > 0)
+    }
+
+    /// The file, line and text of the selected result, to find it again after the results change.
+    fn selection_anchor(&self) -> Option<(String, i32, String)> {
+        let index = self.selection_index?;
+        let job = self.job.as_ref()?;
+        let (path, line_number) = job.get_result(index);
+        Some((
+            path.to_string(),
+            line_number,
+            job.get_text(index).to_string(),
+        ))
+    }
+
+    /// The selection to keep when the results change, none when following the top result.
+    fn kept_selection(&self) -> Option<(String, i32, String)> {
+        match self.auto_selected {
+            true => None,
+            false => self.selection_anchor(),
+        }
+    }
+
     fn select(&mut self, selection: Option<usize>) -> Result<()> {
         self.selection_index = selection;
+        self.restored_selection = None;
+        self.auto_selected = false;
         self.should_rerender = true;
         self.update_preview()
     }
@@ -117,7 +665,14 @@ impl<'a> Manager<'a> {
         };
 
         let (file_path, line_number) = job.get_result(index);
-        self.preview_job = Some(preview::PreviewJob::new(file_path, line_number)?);
+        let lines = match job.get_hit_lines(index) {
+            [] => {
+                let span = job.get_text(index).matches('\n').count() as i32;
+                (line_number..=line_number + span).collect()
+            }
+            hit_lines => hit_lines.to_vec(),
+        };
+        self.preview_job = Some(preview::PreviewJob::new(file_path, &lines, &self.theme)?);
         Ok(())
     }
 
@@ -125,19 +680,77 @@ impl<'a> Manager<'a> {
         if self.should_execute {
             self.execute_job()
         } else {
-            self.read_jobs()
+            let stopped_watching = self.patch_job()?;
+            Ok(self.read_jobs()? || stopped_watching)
+        }
+    }
+
+    /// Re-searches the files that changed on disk since the last update. Returns whether watching
+    /// stopped on an error.
+    fn patch_job(&mut self) -> Result<bool> {
+        if let Some(changes) = self.watcher.as_mut().and_then(|w| w.try_take_changes()) {
+            self.changes.extend(changes);
+        }
+        let Some(job) = self.job.as_ref() else {
+            self.changes.clear();
+            return Ok(false);
+        };
+        if self.changes.is_empty() || !job.is_finished() || job.is_patching() {
+            return Ok(false);
+        }
+        let changes = std::mem::take(&mut self.changes);
+
+        let selected = self.selection_anchor();
+        let Some(job) = self.job.as_mut() else {
+            return Ok(false);
+        };
+        let filter = match watch::Filter::new(&self.options) {
+            Ok(filter) => filter,
+            Err(e) => {
+                self.stop_watching(e);
+                return Ok(true);
+            }
+        };
+        let searched_paths = changes
+            .iter()
+            .filter(|p| filter.is_searched(p))
+            .cloned()
+            .collect();
+        match job.patch(&self.backend, &self.options, changes, searched_paths) {
+            Ok(()) => {
+                self.restored_selection = selected;
+                Ok(false)
+            }
+            Err(e) => {
+                self.stop_watching(e);
+                Ok(true)
+            }
         }
     }
 
     fn execute_job(&mut self) -> Result<bool> {
+        // A new search reads the files as they are now.
+        self.changes.clear();
+        self.watch_error = None;
+        if self
+            .watcher
+            .as_ref()
+            .is_some_and(|w| w.roots() != self.options.roots)
+        {
+            self.watch();
+        }
+        let selected = self.kept_selection();
         self.select(None)?;
+        self.restored_selection = selected;
 
         if let Some(mut j) = self.job.take() {
             j.finalize()?;
         }
 
-        if self.options.prompt.len() > 0 {
-            self.job = Some(ripgrep::Job::new(&self.options)?);
+        if !self.options.prompt.is_empty() {
+            let mut job = job::Job::new(&self.backend, &self.options, &self.theme)?;
+            job.set_filter(&self.filter);
+            self.job = Some(job);
         }
 
         self.should_execute = false;
@@ -148,18 +761,69 @@ impl<'a> Manager<'a> {
     fn read_jobs(&mut self) -> Result<bool> {
         let mut should_rerender = self.should_rerender;
 
+        let mut restored_selection = None;
+        let mut top_inserted = false;
         if let Some(j) = self.job.as_mut() {
-            for _ in 1..10 {
-                if j.try_read_next_result()? {
-                    should_rerender = true;
-                } else {
-                    break;
+            if j.read_results(MAX_RESULTS_PER_TICK)? {
+                should_rerender = true;
+            }
+
+            // Results inserted above the selected one push it down, keep it on the same result.
+            let inserted = j.take_inserted();
+            top_inserted = inserted.first() == Some(&0);
+            if let Some(selection) = self
+                .selection_index
+                .as_mut()
+                .filter(|_| !self.auto_selected)
+            {
+                for &position in &inserted {
+                    if position <= *selection {
+                        *selection += 1;
+                    }
+                }
+            }
+
+            // Select the previous result again as soon as it shows up, or the closest one in its
+            // file once the search is done.
+            if let Some((path, line_number, _)) = self.restored_selection.as_ref() {
+                let found = inserted
+                    .iter()
+                    .copied()
+                    .find(|&position| j.get_result(position) == (&path[..], *line_number));
+                if found.is_some() {
+                    self.restored_selection = None;
+                    restored_selection = Some(found);
+                }
+            }
+            if j.is_finished() && !j.is_patching() {
+                if let Some((path, line_number, text)) = self.restored_selection.take() {
+                    restored_selection = Some(j.find(&path, line_number, &text));
                 }
             }
         }
+        if let Some(selection) = restored_selection {
+            self.select(selection)?;
+            should_rerender = true;
+        }
+
+        // The top result changes as better fuzzy matches come in, or when the filter changes.
+        let follow_top = self.selection_index.is_none() || (self.auto_selected && top_inserted);
+        if self.auto_select
+            && follow_top
+            && self.restored_selection.is_none()
+            && self.num_results() > 0
+        {
+            self.select(Some(0))?;
+            self.auto_selected = true;
+            should_rerender = true;
+        }
 
         if let Some(preview_job) = self.preview_job.as_ref() {
-            if let Some(preview) = preview_job.try_recv_preview()? {
+            if let Some(mut preview) = preview_job.try_recv_preview()? {
+                // The cursor starts on the selected match.
+                if let Some(column) = self.selected_match_column() {
+                    preview.set_cursor_column(column);
+                }
                 self.selection_preview = Some(preview);
                 self.preview_job = None;
                 should_rerender = true;
@@ -170,7 +834,7 @@ impl<'a> Manager<'a> {
         Ok(should_rerender)
     }
 
-    pub fn get_list(&self) -> List {
+    pub fn get_list(&self) -> List<'_> {
         match self.job.as_ref() {
             None => List::new(vec![]),
             Some(job) => List::new(job.get_results_items()),
@@ -181,7 +845,7 @@ impl<'a> Manager<'a> {
         ListState::default().with_selected(self.selection_index)
     }
 
-    pub fn get_preview(&self, height: i32) -> Paragraph {
+    pub fn get_preview(&self, height: i32) -> Paragraph<'_> {
         match &self.selection_preview {
             Some(t) => t.get_paragraph(height),
             None => Paragraph::new(""),
@@ -197,8 +861,105 @@ impl<'a> Manager<'a> {
         };
 
         let (file_path, line_number) = job.get_result(index);
-        let command = format!("$EDITOR +{} \"{}\"", line_number, file_path);
+        let line_number = job
+            .get_hit_lines(index)
+            .first()
+            .copied()
+            .unwrap_or(line_number);
+        let command = match line_number {
+            0 => format!("$EDITOR \"{}\"", file_path),
+            _ => format!("$EDITOR +{} \"{}\"", line_number, file_path),
+        };
         let _ = Command::new("sh").arg("-c").arg(command).status();
         true
     }
 }
+
+/// The identifier around `offset` in `text`, or the first one after it. `None` as well when
+/// `offset` isn't between two chars, like the byte offsets of a line that wasn't valid UTF-8.
+fn identifier_at(text: &str, offset: usize) -> Option<&str> {
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_';
+    let offset = offset.min(text.len());
+    let start = match text.get(offset..)?.find(is_identifier) {
+        // Inside an identifier, go back to its start.
+        Some(0) => text[..offset].rfind(|c| !is_identifier(c)).map_or(0, |i| {
+            i + text[i..].chars().next().map_or(1, char::len_utf8)
+        }),
+        Some(i) => offset + i,
+        None => return None,
+    };
+    let end = text[start..]
+        .find(|c| !is_identifier(c))
+        .map_or(text.len(), |i| start + i);
+    Some(&text[start..end])
+}
+
+/// A glob excluding exactly `path`, or everything under it for a `directory`: anchored to the
+/// current directory like rg's globs, with the glob characters of the path escaped.
+fn exclude_glob(path: &str, directory: bool) -> String {
+    let path = Path::new(path);
+    let path = std::env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .unwrap_or(path);
+    let path = path.strip_prefix("./").unwrap_or(path).to_string_lossy();
+    let mut glob = String::from("!/");
+    for c in path.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '{' | '}' | '\\') {
+            glob.push('\\');
+        }
+        glob.push(c);
+    }
+    if directory {
+        glob.push_str("/**");
+    }
+    glob
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ignore::overrides::OverrideBuilder;
+
+    #[test]
+    fn excludes_exactly_the_scoped_path() {
+        let excluded = |glob: String, path: &str| {
+            let mut builder = OverrideBuilder::new(std::env::current_dir().unwrap());
+            let globs = builder.add(&glob).unwrap().build().unwrap();
+            globs.matched(path, false).is_ignore()
+        };
+        assert!(excluded(exclude_glob("Cargo.toml", false), "Cargo.toml"));
+        assert!(!excluded(
+            exclude_glob("Cargo.toml", false),
+            "crates/a/Cargo.toml"
+        ));
+        assert!(excluded(
+            exclude_glob("app/[id].tsx", false),
+            "app/[id].tsx"
+        ));
+        assert!(!excluded(exclude_glob("app/[id].tsx", false), "app/i.tsx"));
+        assert!(excluded(exclude_glob("./src", true), "src/a.rs"));
+        assert!(!excluded(exclude_glob("./src", true), "lib/src/a.rs"));
+        assert!(excluded(
+            exclude_glob("/elsewhere/a.rs", false),
+            "/elsewhere/a.rs"
+        ));
+    }
+
+    #[test]
+    fn finds_the_identifier_at_an_offset() {
+        let text = "let total = compute_sum(&items);";
+        assert_eq!(identifier_at(text, 16), Some("compute_sum"));
+        assert_eq!(identifier_at(text, 12), Some("compute_sum"));
+        assert_eq!(identifier_at(text, 9), Some("compute_sum"));
+        assert_eq!(identifier_at(text, 0), Some("let"));
+        assert_eq!(identifier_at(text, 25), Some("items"));
+        assert_eq!(identifier_at("é_x = 1", 2), Some("é_x"));
+        assert_eq!(identifier_at(text, 31), None);
+
+        // The match offsets are on the raw bytes, each invalid byte became 3 in the lossy text.
+        let text = String::from_utf8_lossy(b"\xff\xfe total");
+        assert_eq!(identifier_at(&text, 1), None);
+        assert_eq!(identifier_at(&text, 3), Some("total"));
+    }
+}
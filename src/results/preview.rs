@@ -1,6 +1,7 @@
+use crate::theme::Theme;
 use ansi_to_tui::IntoText;
 use ratatui::{prelude::*, widgets::*};
-use std::io::{Error, ErrorKind, Read, Result};
+use std::io::{Error, Read, Result};
 use std::process::{Command, Stdio};
 use std::sync::mpsc;
 use std::thread;
@@ -8,19 +9,130 @@ use std::thread;
 pub struct Preview {
     text: Text<'static>,
     line_number: i32,
+    /// Lines scrolled past the ones centered on `line_number`.
+    scroll: i32,
+    /// The line and the character after the line number the cursor is on, shown while the
+    /// preview has the focus.
+    cursor: (usize, usize),
 }
 
 impl Preview {
     pub fn new(text: Text<'static>, line_number: i32) -> Preview {
-        Preview { text, line_number }
+        Preview {
+            text,
+            line_number,
+            scroll: 0,
+            cursor: ((line_number - 1).max(0) as usize, 0),
+        }
+    }
+
+    /// Scrolls by `lines`, stopping once the first line is at the top or the last one halfway
+    /// down a preview `height` lines high.
+    pub fn scroll(&mut self, lines: i32, height: i32) {
+        self.scroll_to(self.top(height) + lines, height);
     }
 
-    pub fn get_paragraph(&self, height: i32) -> Paragraph {
+    fn scroll_to(&mut self, top: i32, height: i32) {
+        let top = top.clamp(0, self.max_top(height));
+        self.scroll = top - (self.line_number - height / 2);
+    }
+
+    /// The first line shown, centering `line_number` until scrolled.
+    fn top(&self, height: i32) -> i32 {
+        (self.line_number + self.scroll - height / 2).clamp(0, self.max_top(height))
+    }
+
+    fn max_top(&self, height: i32) -> i32 {
+        (self.text.lines.len() as i32 - height / 2).max(0)
+    }
+
+    pub fn get_paragraph(&self, height: i32) -> Paragraph<'_> {
         let paragraph = Paragraph::new(self.text.clone());
-        let scroll_y: u16 = std::cmp::max(0, self.line_number - height / 2)
-            .try_into()
-            .unwrap_or(0);
-        paragraph.scroll((scroll_y, 0))
+        paragraph.scroll((self.top(height).try_into().unwrap_or(0), 0))
+    }
+
+    /// Puts the cursor on the `column`th character of its line.
+    pub fn set_cursor_column(&mut self, column: usize) {
+        self.cursor.1 = column.min(self.code(self.cursor.0).chars().count().saturating_sub(1));
+    }
+
+    /// Moves the cursor by `lines` and `columns`, scrolling to keep it shown.
+    pub fn move_cursor(&mut self, lines: i32, columns: i32, height: i32) {
+        let line = (self.cursor.0 as i64 + lines as i64).max(0) as usize;
+        let column = (self.cursor.1 as i64 + columns as i64).max(0) as usize;
+        self.place_cursor(line, column, height);
+    }
+
+    /// Puts the cursor on the character shown `row` lines and `column` cells from the top left.
+    pub fn click(&mut self, row: u16, column: u16, height: i32) {
+        let line = self.top(height) as usize + row as usize;
+        let gutter = gutter_width(&self.line(line));
+        self.place_cursor(line, (column as usize).saturating_sub(gutter), height);
+    }
+
+    fn place_cursor(&mut self, line: usize, column: usize, height: i32) {
+        self.cursor.0 = line.min(self.text.lines.len().saturating_sub(1));
+        self.set_cursor_column(column);
+
+        // The borders take two lines.
+        let shown = (height - 2).max(1);
+        let line = self.cursor.0 as i32;
+        let top = self.top(height);
+        if line < top {
+            self.scroll_to(line, height);
+        } else if line >= top + shown {
+            self.scroll_to(line - shown + 1, height);
+        }
+    }
+
+    /// Where the cursor is shown, from the top left, `None` when scrolled out.
+    pub fn cursor_position(&self, height: i32) -> Option<(u16, u16)> {
+        let row = self.cursor.0 as i32 - self.top(height);
+        if !(0..(height - 2).max(1)).contains(&row) {
+            return None;
+        }
+        let column = gutter_width(&self.line(self.cursor.0)) + self.cursor.1;
+        Some((column.try_into().ok()?, row as u16))
+    }
+
+    /// The identifier under the cursor, or the first one after it on its line.
+    pub fn identifier(&self) -> Option<String> {
+        let code = self.code(self.cursor.0);
+        let offset = code
+            .char_indices()
+            .nth(self.cursor.1)
+            .map_or(code.len(), |(i, _)| i);
+        super::identifier_at(&code, offset).map(str::to_string)
+    }
+
+    fn line(&self, line: usize) -> String {
+        self.text.lines.get(line).map_or(String::new(), |line| {
+            line.spans
+                .iter()
+                .map(|span| span.content.as_ref())
+                .collect()
+        })
+    }
+
+    /// The text of the `line`th line, without its number.
+    fn code(&self, line: usize) -> String {
+        let mut text = self.line(line);
+        text.drain(..gutter_width(&text));
+        text
+    }
+}
+
+/// The width of the line number `bat -n` starts `line` with, and the space after it.
+fn gutter_width(line: &str) -> usize {
+    let after_spaces = line.trim_start_matches(' ');
+    let after_number = after_spaces.trim_start_matches(|c: char| c.is_ascii_digit());
+    if after_number.len() == after_spaces.len() {
+        return 0;
+    }
+    let width = line.len() - after_number.len();
+    match after_number.chars().next() {
+        Some(' ' | '\t') => width + 1,
+        _ => width,
     }
 }
 
@@ -30,12 +142,14 @@ pub struct PreviewJob {
 }
 
 impl PreviewJob {
-    pub fn new(file_path: &str, line_number: i32) -> Result<Self> {
-        let mut command = Self::build_command(file_path, line_number);
+    /// Scrolls to the first of `lines` and highlights all of them.
+    pub fn new(file_path: &str, lines: &[i32], theme: &Theme) -> Result<Self> {
+        let line_number = lines.first().copied().unwrap_or(0);
+        let mut command = Self::build_command(file_path, lines, theme);
         command.stderr(Stdio::null());
         let mut process = command.stdout(Stdio::piped()).spawn()?;
         let Some(mut stdout) = process.stdout.take() else {
-            return Err(Error::new(ErrorKind::Other, "No stdout"));
+            return Err(Error::other("No stdout"));
         };
         let (tx, rx) = mpsc::channel();
 
@@ -56,24 +170,57 @@ impl PreviewJob {
                 let output = maybe_output?;
                 match output.into_text() {
                     Ok(text) => Ok(Some(Preview::new(text, self.line_number))),
-                    _ => Err(Error::new(ErrorKind::Other, "Could not parse output")),
+                    _ => Err(Error::other("Could not parse output")),
                 }
             }
             Err(mpsc::TryRecvError::Empty) => Ok(None),
-            Err(mpsc::TryRecvError::Disconnected) => {
-                Err(Error::new(ErrorKind::Other, "Thread Disconnected"))
-            }
+            Err(mpsc::TryRecvError::Disconnected) => Err(Error::other("Thread Disconnected")),
         }
     }
 
-    fn build_command(file_path: &str, line_number: i32) -> Command {
+    /// A line number of 0 previews the top of the file without highlighting a line.
+    fn build_command(file_path: &str, lines: &[i32], theme: &Theme) -> Command {
         let mut command = Command::new("bat");
+        if theme.color {
+            command.arg("--color=always");
+        } else {
+            command.arg("--color=never");
+        }
+        if let Some(bat_theme) = &theme.bat_theme {
+            command.arg(format!("--theme={}", bat_theme));
+        }
+        command.arg("-n");
+        for line_number in lines.iter().filter(|&&l| l > 0) {
+            command.arg("-H").arg(line_number.to_string());
+        }
+        command.arg(file_path);
         command
-            .arg("--color=always")
-            .arg("-n")
-            .arg("-H")
-            .arg(line_number.to_string())
-            .arg(file_path);
-        command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_identifier_at_the_cursor() {
+        let text = Text::from("   1 fn total() {}\n   2 \n   3 let sum = total();");
+        let mut preview = Preview::new(text, 1);
+        assert_eq!(preview.identifier().as_deref(), Some("fn"));
+        preview.set_cursor_column(4);
+        assert_eq!(preview.identifier().as_deref(), Some("total"));
+        assert_eq!(preview.cursor_position(10), Some((9, 0)));
+
+        // The column is kept within the line, an empty line has none.
+        preview.move_cursor(1, 0, 10);
+        assert_eq!(preview.cursor, (1, 0));
+        assert_eq!(preview.identifier(), None);
+        preview.move_cursor(5, 12, 10);
+        assert_eq!(preview.cursor, (2, 12));
+        assert_eq!(preview.identifier().as_deref(), Some("total"));
+
+        preview.click(0, 7, 10);
+        assert_eq!(preview.cursor, (0, 2));
+        assert_eq!(gutter_width("no number"), 0);
     }
 }
@@ -0,0 +1,417 @@
+//! The prompt's query language: `foo bar -baz path:src/ lang:rust case:no`.
+//!
+//! Plain terms are patterns that must all match a line, `-term` excludes lines matching it,
+//! `path:` and `lang:` (negated with a leading `-`) narrow down the searched files and `case:`
+//! picks between case sensitive (`yes`), insensitive (`no`) and smart case (`smart`) matching.
+//! Terms can be double quoted to include spaces. A term starting with a quote is always a plain
+//! pattern, so `"-> x"` or `"path:"` are searched for as is, and so is a term starting with `\-`,
+//! without the backslash: `\->` searches for `->`.
+//!
+//! Files can also be narrowed down by when they changed, with `modified:` (`hour`, `day`, `week`
+//! or an age like `30m`, `12h`, `2d` or `3w`) and `since:` a git ref, and by their size with
+//! `size:` (`>10k`, `<1M` or `10k..1M`, in bytes with `k`, `M` and `G` suffixes).
+
+use super::backend::SearchResult;
+use super::ripgrep::{Case, Options, SizeRange};
+use ignore::overrides::{Override, OverrideBuilder};
+use regex::{Regex, RegexBuilder};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[derive(Default, Debug, PartialEq)]
+pub struct Query {
+    pub patterns: Vec<String>,
+    pub excluded_patterns: Vec<String>,
+    pub path_globs: Vec<String>,
+    pub types: Vec<String>,
+    pub types_not: Vec<String>,
+    pub case: Option<Case>,
+    pub modified_within: Option<Duration>,
+    pub changed_since: Option<String>,
+    pub size: Option<SizeRange>,
+}
+
+impl Query {
+    pub fn parse(prompt: &str) -> Query {
+        let mut query = Query::default();
+        for (term, literal) in split_terms(prompt) {
+            if literal {
+                query.patterns.push(term);
+                continue;
+            }
+            let (negated, rest) = match term.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (true, rest),
+                _ => (false, &term[..]),
+            };
+
+            match rest.split_once(':') {
+                Some(("path", path)) if !path.is_empty() => {
+                    query.path_globs.extend(path_globs(path, negated));
+                }
+                Some(("lang", lang)) if !lang.is_empty() => match negated {
+                    true => query.types_not.push(lang.to_string()),
+                    false => query.types.push(lang.to_string()),
+                },
+                Some(("modified", age)) if !negated && parse_age(age).is_some() => {
+                    query.modified_within = parse_age(age);
+                }
+                Some(("since", reference)) if !negated && !reference.is_empty() => {
+                    query.changed_since = Some(reference.to_string());
+                }
+                Some(("size", size)) if !negated && parse_size_range(size).is_some() => {
+                    query.size = parse_size_range(size);
+                }
+                Some(("case", case)) if !negated => {
+                    query.case = match case {
+                        "yes" => Some(Case::Sensitive),
+                        "no" => Some(Case::Insensitive),
+                        "smart" => Some(Case::Smart),
+                        _ => query.case,
+                    }
+                }
+                _ if negated => query.excluded_patterns.push(rest.to_string()),
+                _ => query.patterns.push(term),
+            }
+        }
+        query
+    }
+
+    /// Sets the fields of `options` the query controls, using `default_case` without `case:`.
+    ///
+    /// The first pattern is the one the backend searches for, the others are checked on the
+    /// lines it finds.
+    pub fn apply(self, options: &mut Options, default_case: Case) {
+        let mut patterns = self.patterns.into_iter();
+        options.prompt = patterns.next().unwrap_or_default();
+        options.required_patterns = patterns.collect();
+        options.excluded_patterns = self.excluded_patterns;
+        options.path_globs = self.path_globs;
+        options.types = self.types;
+        options.types_not = self.types_not;
+        options.case = self.case.unwrap_or(default_case);
+        options.modified_within = self.modified_within;
+        options.changed_since = self.changed_since;
+        options.size = self.size;
+    }
+}
+
+/// `hour`, `day`, `week` or a number of minutes, hours, days or weeks, as in `30m` or `2d`. Ages
+/// too large to go back from now are invalid.
+fn parse_age(age: &str) -> Option<Duration> {
+    let (count, unit) = match age {
+        "hour" => (1u64, "h"),
+        "day" => (1, "d"),
+        "week" => (1, "w"),
+        _ => {
+            let split = age.find(|c: char| !c.is_ascii_digit())?;
+            (age[..split].parse().ok()?, &age[split..])
+        }
+    };
+    let minutes: u64 = match unit {
+        "m" => 1,
+        "h" => 60,
+        "d" => 60 * 24,
+        "w" => 60 * 24 * 7,
+        _ => return None,
+    };
+    let age = Duration::from_secs(count.checked_mul(minutes)?.checked_mul(60)?);
+    SystemTime::now().checked_sub(age).map(|_| age)
+}
+
+/// `>10k` for bigger than, `<1M` for smaller than or `10k..1M` for a range, ends included.
+fn parse_size_range(size: &str) -> Option<SizeRange> {
+    if let Some(min) = size.strip_prefix('>') {
+        return Some(SizeRange {
+            min: parse_size(min)?.saturating_add(1),
+            max: u64::MAX,
+        });
+    }
+    if let Some(max) = size.strip_prefix('<') {
+        return Some(SizeRange {
+            min: 0,
+            max: parse_size(max)?.checked_sub(1)?,
+        });
+    }
+    let (min, max) = size.split_once("..")?;
+    let min = if min.is_empty() { 0 } else { parse_size(min)? };
+    let max = if max.is_empty() {
+        u64::MAX
+    } else {
+        parse_size(max)?
+    };
+    (min <= max).then_some(SizeRange { min, max })
+}
+
+/// A number of bytes, with an optional `k`, `M` or `G` suffix for powers of 1024.
+fn parse_size(size: &str) -> Option<u64> {
+    let (number, multiplier) = match size.char_indices().last()? {
+        (i, 'k' | 'K') => (&size[..i], 1 << 10),
+        (i, 'm' | 'M') => (&size[..i], 1 << 20),
+        (i, 'g' | 'G') => (&size[..i], 1 << 30),
+        _ => (size, 1),
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Splits on whitespace, keeping double quoted parts together. Each term comes with whether it's
+/// a literal pattern, starting with a quote or an escaped `-`.
+fn split_terms(prompt: &str) -> Vec<(String, bool)> {
+    let mut terms = Vec::new();
+    let mut term = String::new();
+    let mut quoted = false;
+    let mut has_term = false;
+    let mut literal = false;
+    let mut chars = prompt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                literal |= !has_term;
+                quoted = !quoted;
+                has_term = true;
+            }
+            '\\' if !has_term && chars.peek() == Some(&'-') => {
+                literal = true;
+                has_term = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if has_term {
+                    terms.push((std::mem::take(&mut term), literal));
+                }
+                has_term = false;
+                literal = false;
+            }
+            c => {
+                term.push(c);
+                has_term = true;
+            }
+        }
+    }
+    if has_term {
+        terms.push((term, literal));
+    }
+    terms
+}
+
+/// Turns a `path:` qualifier into globs for [`PathFilter`]. Plain text matches anywhere in the
+/// path, a trailing slash means a directory at any depth and anything with glob characters is
+/// used as is.
+fn path_globs(path: &str, negated: bool) -> Vec<String> {
+    let prefix = if negated { "!" } else { "" };
+    if path.contains(['*', '?', '[', '{']) {
+        return vec![format!("{}{}", prefix, path)];
+    }
+    if let Some(dir) = path.strip_suffix('/') {
+        return vec![format!("{}**/{}/**", prefix, dir)];
+    }
+    if negated {
+        // Excluding a directory excludes everything inside it.
+        return vec![format!("!**/*{}*", path)];
+    }
+    vec![format!("**/*{}*", path), format!("**/*{}*/**", path)]
+}
+
+/// Checks the required and excluded patterns on the results the backend found.
+pub struct PostFilter {
+    required: Vec<Regex>,
+    excluded: Vec<Regex>,
+}
+
+impl PostFilter {
+    /// Returns `None` when there is nothing to check. Invalid patterns match nothing.
+    pub fn new(options: &Options) -> Option<PostFilter> {
+        if options.required_patterns.is_empty() && options.excluded_patterns.is_empty() {
+            return None;
+        }
+        let build = |pattern: &String| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(options.case.is_insensitive(pattern))
+                .build()
+                .unwrap_or_else(|_| Regex::new(r"[^\s\S]").unwrap())
+        };
+        Some(PostFilter {
+            required: options.required_patterns.iter().map(build).collect(),
+            excluded: options.excluded_patterns.iter().map(build).collect(),
+        })
+    }
+
+    /// Keeps `result` if it matches all required patterns and no excluded one, highlighting the
+    /// required patterns' matches.
+    pub fn matches(&self, mut result: SearchResult) -> Option<SearchResult> {
+        if self.excluded.iter().any(|r| r.is_match(&result.text)) {
+            return None;
+        }
+        for regex in &self.required {
+            let len = result.matches.len();
+            result
+                .matches
+                .extend(regex.find_iter(&result.text).map(|m| m.range()));
+            if result.matches.len() == len {
+                return None;
+            }
+        }
+        result.matches.sort_by_key(|m| m.start);
+        Some(result)
+    }
+}
+
+/// Checks the `path:` globs on the paths of the results. A path must match one of the globs if
+/// any isn't negated, and none of the negated ones, whatever the globs the backend was given.
+pub struct PathFilter {
+    /// `None` when a glob is invalid, matching nothing.
+    globs: Option<Override>,
+    roots: Vec<PathBuf>,
+}
+
+impl PathFilter {
+    /// Returns `None` when there is nothing to check.
+    pub fn new(options: &Options) -> Option<PathFilter> {
+        if options.path_globs.is_empty() {
+            return None;
+        }
+        let mut builder = OverrideBuilder::new(std::env::current_dir().unwrap_or_default());
+        let globs = options
+            .path_globs
+            .iter()
+            .try_fold(&mut builder, |builder, glob| builder.add(glob))
+            .and_then(|builder| builder.build())
+            .ok();
+        let roots = options
+            .roots
+            .iter()
+            .map(|root| PathBuf::from(root.strip_prefix("./").unwrap_or(root)))
+            .collect();
+        Some(PathFilter { globs, roots })
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        let Some(globs) = &self.globs else {
+            return false;
+        };
+        let path = Path::new(path);
+        // Like rg, excluding a directory under the roots excludes everything inside it.
+        let excluded_dir = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| !dir.as_os_str().is_empty() && !self.roots.iter().any(|r| r == dir))
+            .any(|dir| globs.matched(dir, true).is_ignore());
+        !excluded_dir && !globs.matched(path, false).is_ignore()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::backend::SearchBackend;
+    use super::super::in_process::InProcess;
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn parses_terms_and_qualifiers() {
+        let query = Query::parse(r#"foo "bar baz" -qux path:src/ -path:test lang:rust case:no"#);
+        assert_eq!(
+            query,
+            Query {
+                patterns: vec!["foo".into(), "bar baz".into()],
+                excluded_patterns: vec!["qux".into()],
+                path_globs: vec!["**/src/**".into(), "!**/*test*".into()],
+                types: vec!["rust".into()],
+                types_not: vec![],
+                case: Some(Case::Insensitive),
+                modified_within: None,
+                changed_since: None,
+                size: None,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_file_filters() {
+        let query = Query::parse("fix modified:2d since:main~3 size:10k..1M");
+        assert_eq!(query.patterns, vec!["fix"]);
+        assert_eq!(
+            query.modified_within,
+            Some(Duration::from_secs(2 * 24 * 3600))
+        );
+        assert_eq!(query.changed_since.as_deref(), Some("main~3"));
+        assert_eq!(
+            query.size,
+            Some(SizeRange {
+                min: 10 << 10,
+                max: 1 << 20
+            })
+        );
+
+        assert_eq!(parse_age("hour"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_age("99999999999999999w"), None);
+        assert_eq!(parse_size_range(">1k").map(|s| s.min), Some(1025));
+        assert_eq!(parse_size_range("<1").map(|s| s.max), Some(0));
+        assert_eq!(parse_size_range("<0"), None);
+        assert_eq!(parse_size_range("2k..1k"), None);
+        // Invalid values are searched for as is.
+        let query = Query::parse("modified:soon size:big");
+        assert_eq!(query.patterns, vec!["modified:soon", "size:big"]);
+    }
+
+    #[test]
+    fn keeps_quoted_and_escaped_terms_literal() {
+        let query = Query::parse(r#""->" \-> "path:x" -"y""#);
+        assert_eq!(query.patterns, vec!["->", "->", "path:x"]);
+        assert_eq!(query.excluded_patterns, vec!["y"]);
+        assert!(query.path_globs.is_empty());
+    }
+
+    #[test]
+    fn keeps_unknown_qualifiers_and_lone_dashes_as_patterns() {
+        let query = Query::parse("http://x - -lang:js fn::new");
+        assert_eq!(query.patterns, vec!["http://x", "-", "fn::new"]);
+        assert_eq!(query.types_not, vec!["js"]);
+    }
+
+    #[test]
+    fn path_filter_narrows_down_the_globbed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in ["src/a.rs", "src/b.md", "docs/c.rs", "src/tests/d.rs"] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "fn x() {}\n").unwrap();
+        }
+        let mut options = Options {
+            roots: vec![dir.path().to_string_lossy().into_owned()],
+            glob: "*.rs".into(),
+            ..Default::default()
+        };
+        Query::parse("fn path:src/ -path:tests").apply(&mut options, Case::default());
+
+        let filter = PathFilter::new(&options).unwrap();
+        let search = InProcess::with_index(None).search(&options).unwrap();
+        let mut paths: Vec<String> =
+            std::iter::from_fn(|| search.recv_timeout(Duration::from_secs(10)).ok())
+                .map(|result| result.path)
+                .filter(|path| filter.matches(path))
+                .collect();
+        paths.sort();
+        assert_eq!(paths, [dir.path().join("src/a.rs").to_string_lossy()]);
+    }
+
+    #[test]
+    fn post_filter_requires_all_and_excludes_any() {
+        let mut options = Options::default();
+        Query::parse("TODO auth -test case:no").apply(&mut options, Case::default());
+        assert_eq!(options.prompt, "TODO");
+        let filter = PostFilter::new(&options).unwrap();
+
+        let result = |text: &str| SearchResult {
+            path: "a.rs".into(),
+            line_number: 1,
+            column: 1,
+            text: text.into(),
+            matches: Vec::new(),
+            score: 0,
+            hit_lines: Vec::new(),
+        };
+        let kept = filter.matches(result("TODO: AUTH tokens, auth")).unwrap();
+        assert_eq!(kept.matches, vec![6..10, 19..23]);
+        assert!(filter.matches(result("TODO: tokens")).is_none());
+        assert!(filter.matches(result("TODO: auth test")).is_none());
+    }
+}
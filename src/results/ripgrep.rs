@@ -1,141 +1,237 @@
-use ansi_to_tui::IntoText;
-use ratatui::widgets::*;
-use std::io::{BufRead, BufReader, Error, ErrorKind, Result};
-use std::process::{Child, Command, Stdio};
-use std::str::FromStr;
-use std::sync::mpsc;
-use std::thread;
+use super::backend::{OutputFormat, Search, SearchBackend};
+use ignore::types::TypesBuilder;
+use std::io::Result;
+use std::process::Command;
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// Search the contents of files
+    #[default]
+    Content,
+    /// Fuzzy match the prompt against file paths
+    FuzzyFiles,
+    /// Match the prompt as a regex against file paths
+    RegexFiles,
+    /// Files matching all the query's patterns and none of its excluded ones
+    BooleanFiles,
+}
+
+impl Mode {
+    pub fn next(self) -> Mode {
+        match self {
+            Mode::Content => Mode::FuzzyFiles,
+            Mode::FuzzyFiles => Mode::RegexFiles,
+            Mode::RegexFiles => Mode::BooleanFiles,
+            Mode::BooleanFiles => Mode::Content,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Content => "Content",
+            Mode::FuzzyFiles => "Files (fuzzy)",
+            Mode::RegexFiles => "Files (regex)",
+            Mode::BooleanFiles => "Files (boolean)",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Case {
+    #[default]
+    Sensitive,
+    Insensitive,
+    /// Insensitive unless the pattern has an uppercase letter
+    Smart,
+}
+
+impl Case {
+    pub fn is_insensitive(self, pattern: &str) -> bool {
+        match self {
+            Case::Sensitive => false,
+            Case::Insensitive => true,
+            Case::Smart => !pattern.chars().any(char::is_uppercase),
+        }
+    }
+}
 
+#[derive(Clone, Default)]
 pub struct Options {
+    pub mode: Mode,
     pub show_hidden: bool,
     pub prompt: String,
     pub glob: String,
+    /// Paths to search, the current directory when empty.
+    pub roots: Vec<String>,
+    /// Let matches span several lines, with `.` matching newlines (`-U --multiline-dotall`).
+    pub multiline: bool,
+
+    /// Patterns a matching line (or file in the boolean mode) must also match, see
+    /// [`super::query`].
+    pub required_patterns: Vec<String>,
+    /// Patterns a matching line must not match.
+    pub excluded_patterns: Vec<String>,
+    /// Globs from `path:` qualifiers. Unlike the ones in `glob`, which rg ORs together, they
+    /// narrow the results down further, see [`super::query::PathFilter`].
+    pub path_globs: Vec<String>,
+    /// Negated globs of the paths excluded with [`super::Scope`]s, added last.
+    pub scope_globs: Vec<String>,
+    /// File types (as in `rg --type-list`) to search, and to skip.
+    pub types: Vec<String>,
+    pub types_not: Vec<String>,
+    /// Custom file types, as `name:glob` for `--type-add`.
+    pub type_definitions: Vec<String>,
+    pub case: Case,
+
+    /// Only files modified this recently, see [`super::candidates`].
+    pub modified_within: Option<Duration>,
+    /// Only files changed since this git ref, committed or not, and untracked ones.
+    pub changed_since: Option<String>,
+    /// Only files with a size in this range.
+    pub size: Option<SizeRange>,
 }
 
-pub struct Job<'a> {
-    process: Child,
-    rx: mpsc::Receiver<Vec<u8>>,
-
-    results_items: Vec<ListItem<'a>>,
-    results_files: Vec<String>,
-    results_lines: Vec<i32>,
+/// File sizes in bytes, both ends included.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SizeRange {
+    pub min: u64,
+    pub max: u64,
 }
 
-impl<'a> Job<'a> {
-    pub fn new(options: &Options) -> Result<Self> {
-        let mut command = Self::build_command(options);
-        command.stderr(Stdio::null());
-        let mut process = command.stdout(Stdio::piped()).spawn()?;
-        let Some(stdout) = process.stdout.take() else {
-            return Err(Error::new(ErrorKind::Other, "No stdout"));
-        };
-
-        let (tx, rx) = mpsc::channel();
-        thread::spawn(move || {
-            let mut reader = BufReader::new(stdout);
-
-            loop {
-                let mut line: Vec<u8> = Vec::new();
-                let num_bytes = reader.read_until(b'\n', &mut line).unwrap_or(0);
-                if num_bytes == 0 {
-                    break;
-                }
-                if let Err(_) = tx.send(line) {
-                    break;
-                }
-            }
-        });
-
-        Ok(Job {
-            process,
-            rx,
-
-            results_items: Vec::new(),
-            results_files: Vec::new(),
-            results_lines: Vec::new(),
-        })
+impl SizeRange {
+    pub fn contains(&self, size: u64) -> bool {
+        (self.min..=self.max).contains(&size)
     }
+}
 
-    pub fn get_results_items(&self) -> &[ListItem] {
-        &self.results_items[..]
+impl Options {
+    /// Whether the searched files are narrowed down by their modification time or size.
+    pub fn has_file_filters(&self) -> bool {
+        self.modified_within.is_some() || self.changed_since.is_some() || self.size.is_some()
     }
 
-    pub fn get_result(&self, index: usize) -> (&str, i32) {
-        (&self.results_files[index], self.results_lines[index])
+    /// The non-empty entries of the `;` separated glob, then the scope globs.
+    pub fn globs(&self) -> impl Iterator<Item = &str> {
+        self.glob
+            .split(';')
+            .map(str::trim)
+            .filter(|g| !g.is_empty())
+            .chain(self.scope_globs.iter().map(String::as_str))
     }
+}
 
-    pub fn current_num_results(&self) -> usize {
-        self.results_items.len()
-    }
+/// A file type that `--type` and `--type-not` take, with the globs of its files.
+pub struct FileType {
+    pub name: String,
+    pub globs: Vec<String>,
+}
 
-    pub fn try_read_next_result(&mut self) -> Result<bool> {
-        match self.rx.try_recv() {
-            Ok(line) => {
-                self.read_next_result(line)?;
-                Ok(true)
-            }
-            Err(mpsc::TryRecvError::Disconnected) => {
-                self.finalize()?;
-                Ok(false)
+/// The file types rg knows, with the custom `definitions`, from `rg --type-list` or from the same
+/// built-in list when rg can't be run.
+pub fn file_types(definitions: &[String]) -> Vec<FileType> {
+    let mut command = Command::new("rg");
+    for definition in definitions {
+        command.arg("--type-add").arg(definition);
+    }
+    match command.arg("--type-list").output() {
+        Ok(output) if output.status.success() => {
+            parse_type_list(&String::from_utf8_lossy(&output.stdout))
+        }
+        _ => {
+            let mut types = TypesBuilder::new();
+            types.add_defaults();
+            for definition in definitions {
+                // Invalid definitions are reported by the search itself.
+                let _ = types.add_def(definition);
             }
-            _ => Ok(false),
+            types
+                .definitions()
+                .into_iter()
+                .map(|definition| FileType {
+                    name: definition.name().to_string(),
+                    globs: definition.globs().to_vec(),
+                })
+                .collect()
         }
     }
+}
 
-    fn read_next_result(&mut self, line: Vec<u8>) -> Result<()> {
-        let Ok(text) = line.into_text() else {
-            return Err(Error::new(ErrorKind::InvalidData, "invalid ripgrep output"));
-        };
-        let mut line_iter = line.iter();
-        let Some(first_sep) = line_iter.position(|&c| c == b':') else {
-            return Err(Error::new(ErrorKind::InvalidData, "invalid ripgrep output"));
-        };
-        let Some(second_sep) = line_iter.position(|&c| c == b':') else {
-            return Err(Error::new(ErrorKind::InvalidData, "invalid ripgrep output"));
-        };
-
-        let file_name = Self::parse_bytes(&line[..first_sep])?;
-        let line_number = Self::parse_bytes(&line[first_sep + 1..first_sep + 1 + second_sep])?;
-
-        self.results_items.push(ListItem::new(text));
-        self.results_files.push(file_name);
-        self.results_lines.push(line_number);
-
-        Ok(())
-    }
+/// Parses the `name: glob, glob` lines of `rg --type-list`.
+fn parse_type_list(output: &str) -> Vec<FileType> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (name, globs) = line.split_once(':')?;
+            Some(FileType {
+                name: name.trim().to_string(),
+                globs: globs.split(',').map(|g| g.trim().to_string()).collect(),
+            })
+        })
+        .collect()
+}
+
+pub struct Ripgrep;
 
-    fn parse_bytes<T: FromStr>(s: &[u8]) -> Result<T> {
-        let Ok(string) = String::from_utf8(strip_ansi_escapes::strip(s)) else {
-            return Err(Error::new(ErrorKind::InvalidData, "invalid ripgrep output"));
-        };
-        match string.parse() {
-            Ok(r) => Ok(r),
-            _ => Err(Error::new(ErrorKind::InvalidData, "invalid ripgrep output")),
+impl SearchBackend for Ripgrep {
+    fn search(&self, options: &Options) -> Result<Search> {
+        let mut command = Self::build_command(options);
+        command.arg(match options.case {
+            Case::Sensitive => "--case-sensitive",
+            Case::Insensitive => "--ignore-case",
+            Case::Smart => "--smart-case",
+        });
+        if options.multiline {
+            command.arg("--multiline").arg("--multiline-dotall");
         }
+        command.arg("--json").arg("--regexp").arg(&options.prompt);
+        command.args(&options.roots);
+        Search::spawn(command, OutputFormat::Json, options)
     }
 
-    pub fn finalize(&mut self) -> Result<()> {
-        self.process.kill()?;
-        // TODO: don't wait here?
-        self.process.wait()?;
-        Ok(())
+    fn files(&self, options: &Options) -> Result<Search> {
+        let mut command = Self::build_command(options);
+        command.arg("--files").args(&options.roots);
+        Search::spawn(command, OutputFormat::Paths, options)
     }
+}
 
+impl Ripgrep {
     fn build_command(options: &Options) -> Command {
         let mut command = Command::new("rg");
-        command
-            .arg("--column")
-            .arg("--color=always")
-            .arg(if options.show_hidden {
-                "--hidden"
-            } else {
-                "--no-hidden"
-            });
-
-        for glob in options.glob.split(";") {
-            command.arg("--glob").arg(glob.trim());
+        command.arg(if options.show_hidden {
+            "--hidden"
+        } else {
+            "--no-hidden"
+        });
+
+        for glob in options.globs() {
+            command.arg("--glob").arg(glob);
+        }
+        for definition in &options.type_definitions {
+            command.arg("--type-add").arg(definition);
+        }
+        for file_type in &options.types {
+            command.arg("--type").arg(file_type);
+        }
+        for file_type in &options.types_not {
+            command.arg("--type-not").arg(file_type);
         }
-        command.arg(&options.prompt);
         command
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_type_list() {
+        let types = parse_type_list("rust: *.rs\ntoml: *.toml, Cargo.lock\n");
+        assert_eq!(types.len(), 2);
+        assert_eq!(types[0].name, "rust");
+        assert_eq!(types[1].globs, ["*.toml", "Cargo.lock"]);
+    }
+}
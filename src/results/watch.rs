@@ -0,0 +1,205 @@
+use super::ripgrep::Options;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::types::{Types, TypesBuilder};
+use ignore::Match;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Editors tend to save in several steps, wait for things to settle before re-searching.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Watches the searched roots and reports which files changed on disk.
+pub struct Watcher {
+    _watcher: RecommendedWatcher,
+    rx: mpsc::Receiver<notify::Result<Event>>,
+    current_dir: PathBuf,
+    /// The roots being watched, to watch the new ones when they change.
+    roots: Vec<String>,
+
+    pending: HashSet<String>,
+    last_event: Instant,
+}
+
+impl Watcher {
+    pub fn new(options: &Options) -> Result<Watcher> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).map_err(Error::other)?;
+        if options.roots.is_empty() {
+            watcher
+                .watch(Path::new("."), RecursiveMode::Recursive)
+                .map_err(Error::other)?;
+        }
+        for root in &options.roots {
+            watcher
+                .watch(Path::new(root), RecursiveMode::Recursive)
+                .map_err(Error::other)?;
+        }
+
+        Ok(Watcher {
+            _watcher: watcher,
+            rx,
+            current_dir: std::env::current_dir()?,
+            roots: options.roots.clone(),
+
+            pending: HashSet::new(),
+            last_event: Instant::now(),
+        })
+    }
+
+    pub fn roots(&self) -> &[String] {
+        &self.roots
+    }
+
+    /// The files changed since the last call, as paths relative to the current directory,
+    /// once no new changes came in for a short while.
+    pub fn try_take_changes(&mut self) -> Option<HashSet<String>> {
+        while let Ok(event) = self.rx.try_recv() {
+            let Ok(event) = event else {
+                continue;
+            };
+            if !matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            ) {
+                continue;
+            }
+
+            for path in event.paths {
+                let path = path.strip_prefix(&self.current_dir).unwrap_or(&path);
+                let path = path.strip_prefix("./").unwrap_or(path);
+                self.pending.insert(path.to_string_lossy().into_owned());
+            }
+            self.last_event = Instant::now();
+        }
+
+        if self.pending.is_empty() || self.last_event.elapsed() < DEBOUNCE {
+            return None;
+        }
+        Some(std::mem::take(&mut self.pending))
+    }
+}
+
+/// Decides whether a changed file would be part of a full search with `options`.
+///
+/// Files passed to a backend explicitly skip its ignore rules, so the rules are checked here
+/// before re-searching just the changed files.
+pub struct Filter {
+    show_hidden: bool,
+    current_dir: PathBuf,
+    /// The `.gitignore` and `.ignore` rules of each directory, read as they're needed.
+    ignores: RefCell<HashMap<PathBuf, Gitignore>>,
+    overrides: Override,
+    types: Types,
+}
+
+impl Filter {
+    pub fn new(options: &Options) -> Result<Filter> {
+        let current_dir = std::env::current_dir()?;
+        let mut overrides = OverrideBuilder::new(&current_dir);
+        for glob in options.globs() {
+            overrides.add(glob).map_err(Error::other)?;
+        }
+
+        let mut types = TypesBuilder::new();
+        types.add_defaults();
+        for definition in &options.type_definitions {
+            types.add_def(definition).map_err(Error::other)?;
+        }
+        for file_type in &options.types {
+            types.select(file_type);
+        }
+        for file_type in &options.types_not {
+            types.negate(file_type);
+        }
+
+        Ok(Filter {
+            show_hidden: options.show_hidden,
+            current_dir,
+            ignores: RefCell::new(HashMap::new()),
+            overrides: overrides.build().map_err(Error::other)?,
+            types: types.build().map_err(Error::other)?,
+        })
+    }
+
+    /// Whether the ignore files of the directories from `path` up to the current directory
+    /// ignore it, the closest ones taking precedence as in rg.
+    fn is_ignored(&self, path: &Path) -> bool {
+        let path = self.current_dir.join(path);
+        let mut ignores = self.ignores.borrow_mut();
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&self.current_dir) {
+                break;
+            }
+            let gitignore = ignores.entry(dir.to_path_buf()).or_insert_with(|| {
+                let mut builder = GitignoreBuilder::new(dir);
+                builder.add(dir.join(".gitignore"));
+                builder.add(dir.join(".ignore"));
+                builder.build().unwrap_or_else(|_| Gitignore::empty())
+            });
+            match gitignore.matched_path_or_any_parents(&path, false) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => (),
+            }
+        }
+        false
+    }
+
+    pub fn is_searched(&self, path: &str) -> bool {
+        let path = Path::new(path);
+        if !path.is_file() || path.starts_with(".git") {
+            return false;
+        }
+        if !self.show_hidden
+            && path
+                .iter()
+                .any(|c| c.to_string_lossy().starts_with('.') && c != "." && c != "..")
+        {
+            return false;
+        }
+        if self.overrides.matched(path, false).is_ignore()
+            || self.types.matched(path, false).is_ignore()
+        {
+            return false;
+        }
+        path.is_absolute() || !self.is_ignored(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn skips_the_files_of_other_types() {
+        // Not hidden, unlike the default `.tmp` prefix.
+        let dir = tempfile::Builder::new().prefix("watch").tempdir().unwrap();
+        let rust = dir.path().join("main.rs");
+        let python = dir.path().join("main.py");
+        fs::write(&rust, "fn main() {}\n").unwrap();
+        fs::write(&python, "print()\n").unwrap();
+        let is_searched = |options: Options| {
+            let filter = Filter::new(&options).unwrap();
+            [&rust, &python].map(|path| filter.is_searched(&path.to_string_lossy()))
+        };
+
+        assert_eq!(is_searched(Options::default()), [true, true]);
+        let options = Options {
+            types: vec!["rust".into()],
+            ..Options::default()
+        };
+        assert_eq!(is_searched(options), [true, false]);
+        let options = Options {
+            types_not: vec!["rust".into()],
+            ..Options::default()
+        };
+        assert_eq!(is_searched(options), [false, true]);
+    }
+}
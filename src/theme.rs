@@ -0,0 +1,255 @@
+//! Colors of the UI, picked from a built-in theme and tweaked in the `[theme]` table of the
+//! config:
+//!
+//! ```toml
+//! [theme]
+//! name = "light"
+//! path = "blue bold"
+//! match = "black on #ffd75f"
+//! bat_theme = "GitHub"
+//! ```
+//!
+//! A style is a list of colors and modifiers, the color after `on` being the background. Setting
+//! `NO_COLOR` or passing `--monochrome` ignores all of this and uses the monochrome theme.
+
+use ratatui::prelude::*;
+use serde::Deserialize;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+    /// Only bold, underlines and reversed text, for terminals and recordings without colors.
+    Monochrome,
+}
+
+#[derive(Clone)]
+pub struct Theme {
+    pub border: Style,
+    /// The keys in the footer.
+    pub key: Style,
+    pub path: Style,
+    pub line_number: Style,
+    pub matched: Style,
+    /// The characters matched by the results filter.
+    pub filter: Style,
+    pub selection: Style,
+    /// Secondary text, such as the mode of a saved search.
+    pub muted: Style,
+    /// Passed to bat for the preview.
+    pub bat_theme: Option<String>,
+    /// False to preview files without colors.
+    pub color: bool,
+}
+
+impl Theme {
+    pub fn builtin(name: ThemeName) -> Theme {
+        let fg = |color| Style::default().fg(color);
+        match name {
+            ThemeName::Dark => Theme {
+                border: Style::default(),
+                key: fg(Color::Red),
+                path: fg(Color::Magenta),
+                line_number: fg(Color::Green),
+                matched: fg(Color::Red).add_modifier(Modifier::BOLD),
+                filter: fg(Color::Yellow).add_modifier(Modifier::UNDERLINED),
+                selection: Style::default(),
+                muted: fg(Color::DarkGray),
+                bat_theme: None,
+                color: true,
+            },
+            ThemeName::Light => Theme {
+                border: fg(Color::DarkGray),
+                key: fg(Color::Red),
+                path: fg(Color::Blue),
+                line_number: fg(Color::Green),
+                matched: fg(Color::Red).add_modifier(Modifier::BOLD),
+                filter: fg(Color::Magenta).add_modifier(Modifier::UNDERLINED),
+                selection: Style::default().bg(Color::Gray),
+                muted: fg(Color::Gray),
+                bat_theme: Some("GitHub".to_string()),
+                color: true,
+            },
+            ThemeName::HighContrast => Theme {
+                border: fg(Color::White),
+                key: fg(Color::LightYellow).add_modifier(Modifier::BOLD),
+                path: fg(Color::LightCyan).add_modifier(Modifier::BOLD),
+                line_number: fg(Color::LightGreen),
+                matched: fg(Color::Black)
+                    .bg(Color::LightYellow)
+                    .add_modifier(Modifier::BOLD),
+                filter: fg(Color::LightMagenta).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                selection: Style::default().add_modifier(Modifier::REVERSED),
+                muted: fg(Color::White),
+                bat_theme: None,
+                color: true,
+            },
+            ThemeName::Monochrome => Theme {
+                border: Style::default(),
+                key: Style::default().add_modifier(Modifier::BOLD),
+                path: Style::default().add_modifier(Modifier::BOLD),
+                line_number: Style::default(),
+                matched: Style::default().add_modifier(Modifier::REVERSED),
+                filter: Style::default().add_modifier(Modifier::UNDERLINED),
+                selection: Style::default().add_modifier(Modifier::BOLD),
+                muted: Style::default().add_modifier(Modifier::DIM),
+                bat_theme: None,
+                color: false,
+            },
+        }
+    }
+
+    /// The configured theme, or the monochrome one when `monochrome` or `NO_COLOR` is set.
+    pub fn new(config: &ThemeConfig, monochrome: bool) -> Theme {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        if monochrome || no_color {
+            return Theme::builtin(ThemeName::Monochrome);
+        }
+        Theme::from_config(config)
+    }
+
+    fn from_config(config: &ThemeConfig) -> Theme {
+        let mut theme = Theme::builtin(config.name.unwrap_or_default());
+        let overrides = [
+            (&mut theme.border, config.border),
+            (&mut theme.key, config.key),
+            (&mut theme.path, config.path),
+            (&mut theme.line_number, config.line_number),
+            (&mut theme.matched, config.matched),
+            (&mut theme.filter, config.filter),
+            (&mut theme.selection, config.selection),
+            (&mut theme.muted, config.muted),
+        ];
+        for (style, spec) in overrides {
+            if let Some(StyleSpec(s)) = spec {
+                *style = s;
+            }
+        }
+        if config.bat_theme.is_some() {
+            theme.bat_theme = config.bat_theme.clone();
+        }
+        theme
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::builtin(ThemeName::Dark)
+    }
+}
+
+/// The `[theme]` table of the config, unset styles are the ones of the named theme.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct ThemeConfig {
+    pub name: Option<ThemeName>,
+    pub border: Option<StyleSpec>,
+    pub key: Option<StyleSpec>,
+    pub path: Option<StyleSpec>,
+    pub line_number: Option<StyleSpec>,
+    #[serde(rename = "match")]
+    pub matched: Option<StyleSpec>,
+    pub filter: Option<StyleSpec>,
+    pub selection: Option<StyleSpec>,
+    pub muted: Option<StyleSpec>,
+    pub bat_theme: Option<String>,
+}
+
+impl ThemeConfig {
+    /// Takes the settings of `other`, keeping ours where it has none.
+    pub fn merge(&mut self, other: ThemeConfig) {
+        self.name = other.name.or(self.name);
+        self.border = other.border.or(self.border);
+        self.key = other.key.or(self.key);
+        self.path = other.path.or(self.path);
+        self.line_number = other.line_number.or(self.line_number);
+        self.matched = other.matched.or(self.matched);
+        self.filter = other.filter.or(self.filter);
+        self.selection = other.selection.or(self.selection);
+        self.muted = other.muted.or(self.muted);
+        self.bat_theme = other.bat_theme.or(self.bat_theme.take());
+    }
+}
+
+/// A style written as `bold red on black`.
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct StyleSpec(pub Style);
+
+impl TryFrom<String> for StyleSpec {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<StyleSpec, String> {
+        let mut style = Style::default();
+        let mut words = s.split_whitespace();
+        while let Some(word) = words.next() {
+            let modifier = match &word.to_lowercase()[..] {
+                "bold" => Modifier::BOLD,
+                "dim" => Modifier::DIM,
+                "italic" => Modifier::ITALIC,
+                "underlined" => Modifier::UNDERLINED,
+                "reversed" => Modifier::REVERSED,
+                "crossed_out" => Modifier::CROSSED_OUT,
+                "on" => {
+                    let color = words
+                        .next()
+                        .ok_or(format!("`{}`: no color after `on`", s))?;
+                    style = style.bg(parse_color(color)?);
+                    continue;
+                }
+                _ => {
+                    style = style.fg(parse_color(word)?);
+                    continue;
+                }
+            };
+            style = style.add_modifier(modifier);
+        }
+        Ok(StyleSpec(style))
+    }
+}
+
+fn parse_color(s: &str) -> Result<Color, String> {
+    Color::from_str(s).map_err(|_| format!("unknown color or modifier `{}`", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_styles() {
+        let spec = |s: &str| StyleSpec::try_from(s.to_string()).map(|spec| spec.0);
+        assert_eq!(
+            spec("bold red on #102030"),
+            Ok(Style::default()
+                .fg(Color::Red)
+                .bg(Color::Rgb(0x10, 0x20, 0x30))
+                .add_modifier(Modifier::BOLD))
+        );
+        assert_eq!(
+            spec("light-blue underlined"),
+            Ok(Style::default()
+                .fg(Color::LightBlue)
+                .add_modifier(Modifier::UNDERLINED))
+        );
+        assert_eq!(spec("245"), Ok(Style::default().fg(Color::Indexed(245))));
+        assert!(spec("blod").is_err());
+        assert!(spec("red on").is_err());
+    }
+
+    #[test]
+    fn overrides_the_named_theme() {
+        let config: ThemeConfig = toml::from_str("name = \"light\"\nmatch = \"reversed\"").unwrap();
+        let theme = Theme::from_config(&config);
+        assert_eq!(theme.path, Theme::builtin(ThemeName::Light).path);
+        assert_eq!(
+            theme.matched,
+            Style::default().add_modifier(Modifier::REVERSED)
+        );
+        assert!(!Theme::new(&config, true).color);
+    }
+}